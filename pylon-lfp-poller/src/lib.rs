@@ -0,0 +1,452 @@
+//! Poll multiple Pylontech compatible BMS concurrently using tokio
+//!
+//! A [Poller] owns a number of serial ports, polls the "_get analog value_" command
+//! on each port with its own [Schedule] and publishes a [Snapshot] of every poll
+//! over a [broadcast] channel.
+//!
+//! ```rust, no_run
+//! use std::time::Duration;
+//! use pylon_lfp_poller::{PortConfig, Poller, Schedule};
+//!
+//! # async fn poll_example() {
+//! let mut poller = Poller::new(16);
+//! poller.add_port(PortConfig::new("stack-1", "/dev/ttyUSB0"));
+//! poller.add_port(PortConfig::new("stack-2", "/dev/ttyUSB1").schedule(Schedule {
+//!     interval: Duration::from_secs(30),
+//!     ..Schedule::default()
+//! }));
+//!
+//! let mut snapshots = poller.subscribe();
+//! let _tasks = poller.spawn();
+//! while let Ok(snapshot) = snapshots.recv().await {
+//!     println!("{}: {:?}", snapshot.port, snapshot.data);
+//! }
+//! # }
+//! ```
+
+use std::{
+    fmt::Display,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::{Duration, SystemTime},
+};
+
+use embedded_io_adapters::tokio_1::FromTokio;
+use embedded_io_async::{ErrorType, Read, ReadReady, Write};
+use log::{debug, warn};
+use pylon_lfp_protocol::{
+    AsyncPylontechBms, DecodeOptions, Error, MAX_UNENCODED_PAYLOAD_LEN,
+    commands::{AnalogValueResponse, ScaledPackData},
+    types::ScalingProfile,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    sync::broadcast,
+    task::JoinSet,
+    time::{MissedTickBehavior, interval, sleep, timeout},
+};
+use tokio_serial::SerialPortBuilderExt;
+
+/// When and how a port is polled
+#[derive(Debug, Clone, Copy)]
+pub struct Schedule {
+    /// Time between two polls
+    pub interval: Duration,
+    /// Time to wait for a response
+    pub timeout: Duration,
+    /// Pack to poll, `0xFF` polls all packs
+    pub pack_address: u8,
+    /// Exponents the measurements are transmitted in
+    pub scaling: ScalingProfile,
+}
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(1),
+            pack_address: 0xFF,
+            scaling: ScalingProfile::SPECIFICATION,
+        }
+    }
+}
+
+/// A serial port to poll
+#[derive(Debug, Clone)]
+pub struct PortConfig {
+    /// Name identifying the port in [Snapshot]s
+    pub name: Arc<str>,
+    /// Path of the serial device
+    pub path: String,
+    /// Baud rate
+    pub baud_rate: u32,
+    pub schedule: Schedule,
+}
+impl PortConfig {
+    /// Create a new [PortConfig] with 9600 baud and the default [Schedule]
+    pub fn new(name: &str, path: &str) -> Self {
+        PortConfig {
+            name: name.into(),
+            path: path.into(),
+            baud_rate: 9600,
+            schedule: Schedule::default(),
+        }
+    }
+    /// Set the baud rate
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+    /// Set the [Schedule]
+    pub fn schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+}
+
+/// Result of polling a port
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// [PortConfig::name] of the polled port
+    pub port: Arc<str>,
+    /// Time the poll finished
+    pub timestamp: SystemTime,
+    /// Measurements of every pack reported, or why the poll failed
+    pub data: Result<Arc<[PackSnapshot]>, PollError>,
+}
+
+/// Measurements of a single pack in SI units
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackSnapshot {
+    /// Cell voltages in Volt
+    pub cell_voltages: Vec<f32>,
+    /// Temperatures in Kelvin
+    pub temperatures: Vec<f32>,
+    /// Pack current in Ampere
+    pub pack_current: f32,
+    /// Pack voltage in Volt
+    pub pack_voltage: f32,
+    /// Remaining charge in Ampere-hours
+    pub pack_remaining: f32,
+    /// Total capacity in Ampere-hours
+    pub total_capacity: f32,
+    pub cell_cycles: u16,
+}
+impl From<&ScaledPackData<'_>> for PackSnapshot {
+    fn from(pack: &ScaledPackData<'_>) -> Self {
+        PackSnapshot {
+            cell_voltages: pack.cell_voltages().collect(),
+            temperatures: pack.temperatures().collect(),
+            pack_current: pack.pack_current(),
+            pack_voltage: pack.pack_voltage(),
+            pack_remaining: pack.pack_remaining(),
+            total_capacity: pack.total_capacity(),
+            cell_cycles: pack.cell_cycles(),
+        }
+    }
+}
+
+/// Why polling a port failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PollError {
+    /// The serial port couldn't be opened
+    Open(String),
+    /// The BMS didn't respond in time
+    Timeout,
+    /// Error communicating with the BMS
+    Bms(String),
+}
+impl Display for PollError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PollError::Open(e) => write!(f, "Failed to open port: {e}"),
+            PollError::Timeout => write!(f, "Timeout"),
+            PollError::Bms(e) => write!(f, "{e}"),
+        }
+    }
+}
+impl std::error::Error for PollError {}
+
+/// Polls multiple ports concurrently
+pub struct Poller {
+    ports: Vec<PortConfig>,
+    sender: broadcast::Sender<Snapshot>,
+}
+
+impl Poller {
+    /// Create a new [Poller]
+    ///
+    /// `capacity` is the number of [Snapshot]s buffered for slow subscribers,
+    /// see [broadcast::channel].
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Poller {
+            ports: Vec::new(),
+            sender,
+        }
+    }
+    /// Add a port to poll
+    pub fn add_port(&mut self, config: PortConfig) -> &mut Self {
+        self.ports.push(config);
+        self
+    }
+    /// Subscribe to the [Snapshot]s of all ports
+    pub fn subscribe(&self) -> broadcast::Receiver<Snapshot> {
+        self.sender.subscribe()
+    }
+    /// Spawn a task polling each port on the current tokio runtime
+    ///
+    /// Ports are reopened after transport errors.
+    /// Polling stops when the returned [JoinSet] is dropped.
+    pub fn spawn(self) -> JoinSet<()> {
+        let mut tasks = JoinSet::new();
+        for config in self.ports {
+            tasks.spawn(poll_serial(config, self.sender.clone()));
+        }
+        tasks
+    }
+}
+
+/// Poll a serial port forever, reopening it after transport errors
+async fn poll_serial(config: PortConfig, sender: broadcast::Sender<Snapshot>) {
+    loop {
+        let port = tokio_serial::new(&config.path, config.baud_rate).open_native_async();
+        match port {
+            Ok(port) => {
+                let e = poll_transport(config.name.clone(), port, config.schedule, &sender).await;
+                warn!("Transport error on {}, reopening: {e}", config.name);
+            }
+            Err(e) => {
+                warn!("Failed to open {}: {e}", config.path);
+                publish(&sender, &config.name, Err(PollError::Open(e.to_string())));
+            }
+        }
+        sleep(config.schedule.interval).await;
+    }
+}
+
+/// Poll a BMS on `transport` according to `schedule`
+///
+/// Publishes a [Snapshot] named `name` for every poll on `sender`.
+/// Input left over from a timed out poll (e.g. a late response) is discarded before the next request.
+/// Returns when the transport fails, e.g. because a USB adapter was unplugged.
+pub async fn poll_transport<T: AsyncRead + AsyncWrite + Unpin>(
+    name: Arc<str>,
+    transport: T,
+    schedule: Schedule,
+    sender: &broadcast::Sender<Snapshot>,
+) -> std::io::Error {
+    let mut bms: AsyncPylontechBms<_> = AsyncPylontechBms::new(Transport::new(transport));
+    // Skip the rest of a late response still arriving after draining
+    bms.set_decode_options(DecodeOptions {
+        resync: true,
+        ..DecodeOptions::default()
+    });
+    let mut buf = vec![0u8; MAX_UNENCODED_PAYLOAD_LEN];
+    let mut ticks = interval(schedule.interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        match bms.drain().await {
+            Ok(0) => {}
+            Ok(drained) => debug!("Discarded {drained} stale bytes on {name}"),
+            Err(Error::Transport(e)) => {
+                publish(sender, &name, Err(PollError::Bms(e.to_string())));
+                return e;
+            }
+            Err(_) => {}
+        }
+        let response = timeout(
+            schedule.timeout,
+            bms.get_analog_value(schedule.pack_address, &mut buf),
+        )
+        .await;
+        let data = match response {
+            Ok(Ok(response)) => packs(&response, schedule.scaling),
+            Ok(Err(Error::Transport(e))) => {
+                publish(sender, &name, Err(PollError::Bms(e.to_string())));
+                return e;
+            }
+            Ok(Err(e)) => Err(PollError::Bms(e.to_string())),
+            Err(_) => Err(PollError::Timeout),
+        };
+        publish(sender, &name, data);
+    }
+}
+
+/// Tokio transport implementing [ReadReady] by reading ahead one byte
+struct Transport<T> {
+    inner: FromTokio<T>,
+    /// Byte read by [ReadReady::read_ready]
+    peeked: Option<u8>,
+}
+impl<T> Transport<T> {
+    fn new(inner: T) -> Self {
+        Transport {
+            inner: FromTokio::new(inner),
+            peeked: None,
+        }
+    }
+}
+impl<T> ErrorType for Transport<T> {
+    type Error = std::io::Error;
+}
+impl<T: AsyncRead + Unpin> Read for Transport<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match (self.peeked.take(), buf.first_mut()) {
+            (Some(byte), Some(first)) => {
+                *first = byte;
+                Ok(1)
+            }
+            (peeked, _) => {
+                self.peeked = peeked;
+                self.inner.read(buf).await
+            }
+        }
+    }
+}
+impl<T: AsyncRead + Unpin> ReadReady for Transport<T> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        if self.peeked.is_some() {
+            return Ok(true);
+        }
+        let mut byte = [0u8];
+        let mut buf = ReadBuf::new(&mut byte);
+        let mut cx = Context::from_waker(Waker::noop());
+        match Pin::new(self.inner.inner_mut()).poll_read(&mut cx, &mut buf) {
+            Poll::Ready(Ok(())) => {
+                // The end of the stream is ready to be read as well
+                self.peeked = buf.filled().first().copied();
+                Ok(true)
+            }
+            Poll::Ready(Err(e)) => Err(e),
+            Poll::Pending => Ok(false),
+        }
+    }
+}
+impl<T: AsyncWrite + Unpin> Write for Transport<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.inner.write(buf).await
+    }
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
+
+/// Scale all packs of a response
+fn packs(
+    response: &AnalogValueResponse<'_>,
+    scaling: ScalingProfile,
+) -> Result<Arc<[PackSnapshot]>, PollError> {
+    (0..response.get_pack_count())
+        .map(|i| {
+            response
+                .get_pack_scaled(i, scaling)
+                .map(|pack| PackSnapshot::from(&pack))
+                .map_err(|e| PollError::Bms(format!("Invalid pack data: {e:?}")))
+        })
+        .collect()
+}
+
+/// Send a [Snapshot], ignoring that there might be no subscribers
+fn publish(
+    sender: &broadcast::Sender<Snapshot>,
+    port: &Arc<str>,
+    data: Result<Arc<[PackSnapshot]>, PollError>,
+) {
+    let snapshot = Snapshot {
+        port: port.clone(),
+        timestamp: SystemTime::now(),
+        data,
+    };
+    if sender.send(snapshot).is_err() {
+        debug!("No subscribers for snapshot of {port}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt, duplex},
+        sync::broadcast,
+    };
+
+    use super::{PollError, Schedule, poll_transport};
+
+    /// Analog value response of the specification example
+    const RESPONSE: &[u8] = b"~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\r";
+
+    #[tokio::test(start_paused = true)]
+    async fn publish_snapshots() {
+        let (transport, mut bms) = duplex(1024);
+        let (sender, mut snapshots) = broadcast::channel(4);
+        let schedule = Schedule {
+            pack_address: 1,
+            ..Schedule::default()
+        };
+        let poller = tokio::spawn(async move {
+            poll_transport("stack".into(), transport, schedule, &sender).await
+        });
+
+        // Answer the first request, ignore the second one
+        let mut request = [0u8; 20];
+        bms.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"~28014642E00201FD2D\r");
+        bms.write_all(RESPONSE).await.unwrap();
+
+        let snapshot = snapshots.recv().await.unwrap();
+        assert_eq!(snapshot.port, Arc::from("stack"));
+        let packs = snapshot.data.expect("Poll failed");
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].cell_voltages.len(), 15);
+        assert_eq!(packs[0].cell_cycles, 2);
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        bms.read_exact(&mut request).await.unwrap();
+        let snapshot = snapshots.recv().await.unwrap();
+        assert_eq!(snapshot.data.unwrap_err(), PollError::Timeout);
+
+        // The poller returns once the transport is gone
+        drop(bms);
+        poller.await.unwrap();
+    }
+    #[tokio::test(start_paused = true)]
+    async fn discard_late_response() {
+        let (transport, mut bms) = duplex(1024);
+        let (sender, mut snapshots) = broadcast::channel(4);
+        let schedule = Schedule {
+            pack_address: 1,
+            ..Schedule::default()
+        };
+        let poller = tokio::spawn(async move {
+            poll_transport("stack".into(), transport, schedule, &sender).await
+        });
+
+        // Answer the first request after the timeout
+        let mut request = [0u8; 20];
+        bms.read_exact(&mut request).await.unwrap();
+        let snapshot = snapshots.recv().await.unwrap();
+        assert_eq!(snapshot.data.unwrap_err(), PollError::Timeout);
+        bms.write_all(RESPONSE).await.unwrap();
+
+        // The late response is discarded, the next one belongs to the next request
+        bms.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"~28014642E00201FD2D\r");
+        bms.write_all(&RESPONSE[..40]).await.unwrap();
+        let snapshot = snapshots.recv().await.unwrap();
+        assert_eq!(snapshot.data.unwrap_err(), PollError::Timeout);
+
+        // The rest of a partial late response is skipped
+        bms.read_exact(&mut request).await.unwrap();
+        bms.write_all(&RESPONSE[40..]).await.unwrap();
+        bms.write_all(RESPONSE).await.unwrap();
+        let snapshot = snapshots.recv().await.unwrap();
+        assert_eq!(snapshot.data.expect("Poll failed").len(), 1);
+
+        drop(bms);
+        poller.await.unwrap();
+    }
+}
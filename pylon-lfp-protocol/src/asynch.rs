@@ -0,0 +1,218 @@
+//! Async client over [embedded_io_async] (requires the `async` feature)
+//!
+//! [AsyncPylontechBms] offers the same commands as [PylontechBms](crate::PylontechBms)
+//! and decodes responses identically, but doesn't block the executor
+//! while waiting for the BMS to respond.
+
+use core::marker::PhantomData;
+
+use embedded_io_async::{Read, ReadReady, Write};
+use zerocopy::{FromZeros, IntoBytes};
+
+use crate::{
+    CommandCode, DecodeOptions, DecodeWarnings, Error, Frame, MAX_UNENCODED_PAYLOAD_LEN, Version,
+    commands::SystemParameter,
+    dialect::{Dialect, Pylontech},
+    parse_payload,
+};
+
+/// Pylontech RS232 protocol BMS using async IO
+///
+/// Generic over the [Dialect] spoken by the BMS, defaults to the [Pylontech] specification.
+pub struct AsyncPylontechBms<U: Read + Write, D: Dialect = Pylontech> {
+    uart: U,
+    dialect: PhantomData<D>,
+    options: DecodeOptions,
+    /// Bytes discarded during the last request
+    skipped: usize,
+    /// Deviations tolerated in the last response
+    warnings: DecodeWarnings,
+}
+
+impl<U: Read + Write, D: Dialect> AsyncPylontechBms<U, D> {
+    pub fn new(uart: U) -> Self {
+        AsyncPylontechBms {
+            uart,
+            dialect: PhantomData,
+            options: DecodeOptions::default(),
+            skipped: 0,
+            warnings: DecodeWarnings::default(),
+        }
+    }
+    /// Set the [DecodeOptions] used for responses
+    pub fn set_decode_options(&mut self, options: DecodeOptions) {
+        self.options = options;
+    }
+    /// Number of bytes skipped before the last response (see [DecodeOptions::resync])
+    pub fn skipped_bytes(&self) -> usize {
+        self.skipped
+    }
+    /// Deviations from the specification tolerated in the last response
+    ///
+    /// See [DecodeOptions] for the tolerances.
+    pub fn decode_warnings(&self) -> DecodeWarnings {
+        self.warnings
+    }
+
+    /// Get the protocol version from the BMS
+    pub async fn get_protocol_version(&mut self) -> Result<Version, Error<U::Error>> {
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let response = self
+            .request(
+                D::frame_address(1),
+                CommandCode::GetProtocolVersion,
+                &[],
+                &mut buf,
+            )
+            .await?;
+        Ok(response.ver)
+    }
+
+    /// Get the system parameters
+    pub async fn get_system_parameter(&mut self) -> Result<SystemParameter, Error<U::Error>> {
+        let mut system_parameter = SystemParameter::new_zeroed();
+
+        let buf = system_parameter.as_mut_bytes();
+        self.request(
+            D::frame_address(1),
+            CommandCode::GetSystemParameter,
+            &[],
+            buf,
+        )
+        .await?;
+        Ok(system_parameter)
+    }
+    /// Get analog values
+    ///
+    /// See [PylontechBms::get_analog_value](crate::PylontechBms::get_analog_value).
+    pub async fn get_analog_value<'a>(
+        &mut self,
+        address: u8,
+        paylaod_buf: &'a mut [u8],
+    ) -> Result<D::AnalogValue<'a>, Error<U::Error>> {
+        let len = self
+            .request(
+                D::frame_address(address),
+                CommandCode::GetAnalogValue,
+                &[address],
+                paylaod_buf,
+            )
+            .await?
+            .info
+            .len();
+        parse_payload(&paylaod_buf[..len])
+    }
+    /// Get alarm info
+    ///
+    /// See [PylontechBms::get_alarm_info](crate::PylontechBms::get_alarm_info).
+    pub async fn get_alarm_info<'a>(
+        &mut self,
+        address: u8,
+        paylaod_buf: &'a mut [u8],
+    ) -> Result<D::AlarmInfo<'a>, Error<U::Error>> {
+        let len = self
+            .request(
+                D::frame_address(address),
+                CommandCode::GetAlarmInfo,
+                &[address],
+                paylaod_buf,
+            )
+            .await?
+            .info
+            .len();
+        parse_payload(&paylaod_buf[..len])
+    }
+
+    /// Send a command and receive the response
+    ///
+    /// See [PylontechBms](crate::PylontechBms) for the errors returned.
+    async fn request<'a>(
+        &mut self,
+        adr: u8,
+        command: CommandCode,
+        info: &[u8],
+        payload_buf: &'a mut [u8],
+    ) -> Result<Frame<'a>, Error<U::Error>> {
+        if !D::supports(command) {
+            return Err(Error::UnsupportedCommand);
+        }
+        let packet = Frame::new_with_cid1(D::VERSION, adr, D::CID1, command.into(), info);
+        packet.encode_async(&mut self.uart).await?;
+        self.uart.flush().await?;
+
+        let decoded = Frame::decode_with_async(&mut self.uart, payload_buf, self.options).await?;
+        self.skipped = decoded.skipped;
+        self.warnings = decoded.warnings;
+        let response = decoded.frame;
+        if response.cid1 != D::CID1 {
+            return Err(Error::UnsupportedControlIdentifier);
+        }
+        Ok(response)
+    }
+}
+
+impl<U: Read + Write + ReadReady, D: Dialect> AsyncPylontechBms<U, D> {
+    /// Discard all input received so far
+    ///
+    /// Call before a request to drop stale input (e.g. a late response to a timed out request).
+    /// Returns the number of bytes discarded.
+    pub async fn drain(&mut self) -> Result<usize, Error<U::Error>> {
+        let mut buf = [0u8; 32];
+        let mut drained = 0;
+        while self.uart.read_ready()? {
+            let read = self.uart.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            drained += read;
+        }
+        Ok(drained)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncPylontechBms;
+    use crate::{MAX_UNENCODED_PAYLOAD_LEN, commands::PackData};
+
+    /// Transport answering with a fixed response
+    struct Responder {
+        response: &'static [u8],
+        written: Vec<u8>,
+    }
+    impl embedded_io_async::ErrorType for Responder {
+        type Error = core::convert::Infallible;
+    }
+    impl embedded_io_async::Read for Responder {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            embedded_io_async::Read::read(&mut self.response, buf).await
+        }
+    }
+    impl embedded_io_async::Write for Responder {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn get_analog_value() {
+        let uart = Responder {
+            response: b"~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\r",
+            written: Vec::new(),
+        };
+        let mut bms: AsyncPylontechBms<_> = AsyncPylontechBms::new(uart);
+
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let response = embassy_futures::block_on(bms.get_analog_value(1, &mut buf))
+            .expect("Failed to get analog value");
+        assert_eq!(bms.uart.written, b"~28014642E00201FD2D\r");
+
+        let pack: PackData<'_> = response.get_pack(0).expect("Failed to parse PackData");
+        assert_eq!(pack.cell_voltages.len(), 15);
+        assert_eq!(pack.pack_voltage.get_raw(), 50981);
+    }
+}
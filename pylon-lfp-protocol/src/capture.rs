@@ -0,0 +1,584 @@
+//! Recording and replaying transports
+//!
+//! [Recorder] wraps a transport and logs all traffic to a capture,
+//! [Replay] feeds a capture back into [PylontechBms](crate::PylontechBms)
+//! to reproduce a session offline.
+//!
+//! # Capture format
+//!
+//! A capture is ASCII text with one record per line, lines starting with `#` are comments:
+//!
+//! ```text
+//! # pylon-lfp capture v1
+//! 0 tx ~28014642E00201FD2D\r
+//! 84211 rx ~20014600C06E11010F0D45...0002E553\r
+//! 1084950 rx-error TimedOut
+//! ```
+//!
+//! Every record starts with a timestamp in microseconds since the start of the recording,
+//! followed by a space and the event:
+//! - `tx <data>`: bytes written to the BMS
+//! - `rx <data>`: bytes read from the BMS
+//! - `rx-error <kind>`: reading failed with the [ErrorKind] `kind` (e.g. `TimedOut`)
+//!
+//! `data` is escaped like [`<[u8]>::escape_ascii`](slice::escape_ascii):
+//! printable ASCII except `\`, `'` and `"` is kept, `\t`, `\r`, `\n`, `\\`, `\'` and `\"`
+//! are escaped with a backslash, any other byte as `\xNN`.
+//! Consecutive chunks of the same direction are merged into one record,
+//! a record ends when the direction changes or the transport is flushed.
+
+use core::fmt::Display;
+
+use embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write, WriteFmtError};
+
+/// First line of a capture written by [Recorder]
+pub const HEADER: &str = "# pylon-lfp capture v1";
+
+/// Names of the [ErrorKind]s in `rx-error` records
+const ERROR_KINDS: [(&str, ErrorKind); 18] = [
+    ("Other", ErrorKind::Other),
+    ("NotFound", ErrorKind::NotFound),
+    ("PermissionDenied", ErrorKind::PermissionDenied),
+    ("ConnectionRefused", ErrorKind::ConnectionRefused),
+    ("ConnectionReset", ErrorKind::ConnectionReset),
+    ("ConnectionAborted", ErrorKind::ConnectionAborted),
+    ("NotConnected", ErrorKind::NotConnected),
+    ("AddrInUse", ErrorKind::AddrInUse),
+    ("AddrNotAvailable", ErrorKind::AddrNotAvailable),
+    ("BrokenPipe", ErrorKind::BrokenPipe),
+    ("AlreadyExists", ErrorKind::AlreadyExists),
+    ("InvalidInput", ErrorKind::InvalidInput),
+    ("InvalidData", ErrorKind::InvalidData),
+    ("TimedOut", ErrorKind::TimedOut),
+    ("Interrupted", ErrorKind::Interrupted),
+    ("Unsupported", ErrorKind::Unsupported),
+    ("OutOfMemory", ErrorKind::OutOfMemory),
+    ("WriteZero", ErrorKind::WriteZero),
+];
+
+/// Name of `kind` in `rx-error` records
+fn error_kind_name(kind: ErrorKind) -> &'static str {
+    ERROR_KINDS
+        .iter()
+        .find(|(_, k)| *k == kind)
+        .map_or("Other", |(name, _)| name)
+}
+
+/// Errors encountered while parsing a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureParseError {
+    /// The timestamp of the record on `line` is missing or not a number
+    InvalidTimestamp { line: usize },
+    /// The event of the record on `line` is unknown
+    InvalidEvent { line: usize },
+    /// The data of the record on `line` contains an invalid escape sequence
+    InvalidData { line: usize },
+}
+impl Display for CaptureParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CaptureParseError::InvalidTimestamp { line } => {
+                write!(f, "Line {line}: invalid timestamp")
+            }
+            CaptureParseError::InvalidEvent { line } => write!(f, "Line {line}: invalid event"),
+            CaptureParseError::InvalidData { line } => write!(f, "Line {line}: invalid data"),
+        }
+    }
+}
+
+/// A record of a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// Microseconds since the start of the recording
+    pub timestamp: u64,
+    pub event: Event<'a>,
+}
+
+/// Event of a [Record]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// Bytes written to the BMS
+    Tx(Data<'a>),
+    /// Bytes read from the BMS
+    Rx(Data<'a>),
+    /// Reading failed
+    RxError(ErrorKind),
+}
+
+/// Escaped data of a [Record]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Data<'a>(&'a [u8]);
+impl<'a> Data<'a> {
+    /// The unescaped bytes
+    pub fn bytes(&self) -> Unescape<'a> {
+        Unescape(self.0)
+    }
+}
+impl core::fmt::Debug for Data<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "\"{}\"", self.0.escape_ascii())
+    }
+}
+
+/// Iterator over the unescaped bytes of [Data]
+#[derive(Debug, Clone)]
+pub struct Unescape<'a>(&'a [u8]);
+impl Unescape<'_> {
+    /// Unescape the next byte, `Err` on invalid escape sequences
+    fn try_next(&mut self) -> Option<Result<u8, ()>> {
+        let (&first, rest) = self.0.split_first()?;
+        self.0 = rest;
+        if first != b'\\' {
+            return Some(if first.is_ascii_graphic() || first == b' ' {
+                Ok(first)
+            } else {
+                Err(())
+            });
+        }
+        let Some((&escaped, rest)) = self.0.split_first() else {
+            return Some(Err(()));
+        };
+        self.0 = rest;
+        Some(match escaped {
+            b't' => Ok(b'\t'),
+            b'r' => Ok(b'\r'),
+            b'n' => Ok(b'\n'),
+            b'\\' | b'\'' | b'"' => Ok(escaped),
+            b'x' => self.hex_byte(),
+            _ => Err(()),
+        })
+    }
+    /// Unescape the two hex digits following `\x`
+    fn hex_byte(&mut self) -> Result<u8, ()> {
+        let hex = self.0.get(..2).ok_or(())?;
+        self.0 = &self.0[2..];
+        core::str::from_utf8(hex)
+            .ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or(())
+    }
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+impl Iterator for Unescape<'_> {
+    type Item = u8;
+
+    /// Invalid escape sequences are skipped, [Records] rejects them
+    fn next(&mut self) -> Option<u8> {
+        loop {
+            if let Ok(byte) = self.try_next()? {
+                return Some(byte);
+            }
+        }
+    }
+}
+
+/// Iterator over the [Record]s of a capture
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    lines: core::slice::Split<'a, u8, fn(&u8) -> bool>,
+    line: usize,
+}
+impl<'a> Records<'a> {
+    pub fn new(capture: &'a [u8]) -> Self {
+        Records {
+            lines: capture.split(|b| *b == b'\n'),
+            line: 0,
+        }
+    }
+    fn parse(&self, line: &'a [u8]) -> Result<Record<'a>, CaptureParseError> {
+        let line_number = self.line;
+        let (timestamp, event) = split_at_space(line)
+            .ok_or(CaptureParseError::InvalidTimestamp { line: line_number })?;
+        let timestamp = core::str::from_utf8(timestamp)
+            .ok()
+            .and_then(|timestamp| timestamp.parse().ok())
+            .ok_or(CaptureParseError::InvalidTimestamp { line: line_number })?;
+        let (name, argument) = split_at_space(event).unwrap_or((event, &[]));
+        let data = || {
+            let mut unescape = Unescape(argument);
+            while let Some(byte) = unescape.try_next() {
+                byte.map_err(|_| CaptureParseError::InvalidData { line: line_number })?;
+            }
+            Ok(Data(argument))
+        };
+        let event = match name {
+            b"tx" => Event::Tx(data()?),
+            b"rx" => Event::Rx(data()?),
+            b"rx-error" => ERROR_KINDS
+                .iter()
+                .find(|(kind, _)| kind.as_bytes() == argument)
+                .map(|(_, kind)| Event::RxError(*kind))
+                .ok_or(CaptureParseError::InvalidEvent { line: line_number })?,
+            _ => return Err(CaptureParseError::InvalidEvent { line: line_number }),
+        };
+        Ok(Record { timestamp, event })
+    }
+}
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, CaptureParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if !line.is_empty() && !line.starts_with(b"#") {
+                return Some(self.parse(line));
+            }
+        }
+    }
+}
+
+/// Split `line` at the first space
+fn split_at_space(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let space = line.iter().position(|b| *b == b' ')?;
+    Some((&line[..space], &line[space + 1..]))
+}
+
+/// Error of a [Recorder]
+#[derive(Debug)]
+pub enum RecordError<T, C> {
+    /// Error of the wrapped transport
+    Transport(T),
+    /// Error writing the capture
+    Capture(C),
+}
+impl<T: Display, C: Display> Display for RecordError<T, C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RecordError::Transport(e) => write!(f, "{e}"),
+            RecordError::Capture(e) => write!(f, "Failed to write capture: {e}"),
+        }
+    }
+}
+impl<T: embedded_io::Error, C: embedded_io::Error> core::error::Error for RecordError<T, C> {}
+impl<T: embedded_io::Error, C: embedded_io::Error> embedded_io::Error for RecordError<T, C> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            RecordError::Transport(e) => e.kind(),
+            RecordError::Capture(e) => e.kind(),
+        }
+    }
+}
+
+/// Direction of the record being written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Open {
+    None,
+    Tx,
+    Rx,
+}
+
+/// Transport wrapper logging all traffic to a capture
+///
+/// `clock` returns the microseconds since the start of the recording.
+/// Call [Recorder::finish] to terminate the last record.
+pub struct Recorder<T, W, C> {
+    inner: T,
+    capture: W,
+    clock: C,
+    header: bool,
+    open: Open,
+}
+
+impl<T: ErrorType, W: Write, C: FnMut() -> u64> Recorder<T, W, C> {
+    pub fn new(inner: T, capture: W, clock: C) -> Self {
+        Recorder {
+            inner,
+            capture,
+            clock,
+            header: false,
+            open: Open::None,
+        }
+    }
+    /// Terminate the last record and return the transport and the capture
+    pub fn finish(mut self) -> Result<(T, W), W::Error> {
+        self.close()?;
+        self.capture.flush()?;
+        Ok((self.inner, self.capture))
+    }
+    /// Append `data` to the open record of `direction` or start a new one
+    fn record(
+        &mut self,
+        direction: Open,
+        data: &[u8],
+    ) -> Result<(), RecordError<T::Error, W::Error>> {
+        if self.open != direction {
+            self.close().map_err(RecordError::Capture)?;
+            let event = if direction == Open::Tx { "tx" } else { "rx" };
+            let timestamp = (self.clock)();
+            write_record(&mut self.capture, format_args!("{timestamp} {event} "))?;
+            self.open = direction;
+        }
+        write_record(&mut self.capture, format_args!("{}", data.escape_ascii()))
+    }
+    fn record_error(&mut self, kind: ErrorKind) -> Result<(), RecordError<T::Error, W::Error>> {
+        self.close().map_err(RecordError::Capture)?;
+        let timestamp = (self.clock)();
+        let kind = error_kind_name(kind);
+        write_record(
+            &mut self.capture,
+            format_args!("{timestamp} rx-error {kind}\n"),
+        )
+    }
+    /// Terminate the open record, writes the header before the first record
+    fn close(&mut self) -> Result<(), W::Error> {
+        if !self.header {
+            self.capture.write_all(HEADER.as_bytes())?;
+            self.capture.write_all(b"\n")?;
+            self.header = true;
+        }
+        if self.open != Open::None {
+            self.capture.write_all(b"\n")?;
+            self.open = Open::None;
+        }
+        Ok(())
+    }
+}
+
+/// Write formatted numbers and escaped bytes to the capture
+fn write_record<T, W: Write>(
+    capture: &mut W,
+    args: core::fmt::Arguments<'_>,
+) -> Result<(), RecordError<T, W::Error>> {
+    capture.write_fmt(args).map_err(|e| match e {
+        WriteFmtError::Other(e) => RecordError::Capture(e),
+        WriteFmtError::FmtError => unreachable!("Formatting numbers and escaped bytes can't fail"),
+    })
+}
+
+impl<T: ErrorType, W: Write, C> ErrorType for Recorder<T, W, C> {
+    type Error = RecordError<T::Error, W::Error>;
+}
+impl<T: Read, W: Write, C: FnMut() -> u64> Read for Recorder<T, W, C> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self.inner.read(buf) {
+            Ok(len) => {
+                if len > 0 {
+                    self.record(Open::Rx, &buf[..len])?;
+                }
+                Ok(len)
+            }
+            Err(e) => {
+                self.record_error(embedded_io::Error::kind(&e))?;
+                Err(RecordError::Transport(e))
+            }
+        }
+    }
+}
+impl<T: ReadReady, W: Write, C: FnMut() -> u64> ReadReady for Recorder<T, W, C> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        self.inner.read_ready().map_err(RecordError::Transport)
+    }
+}
+impl<T: Write, W: Write, C: FnMut() -> u64> Write for Recorder<T, W, C> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let len = self.inner.write(buf).map_err(RecordError::Transport)?;
+        self.record(Open::Tx, &buf[..len])?;
+        Ok(len)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().map_err(RecordError::Transport)?;
+        if self.open == Open::Tx {
+            self.close().map_err(RecordError::Capture)?;
+        }
+        self.capture.flush().map_err(RecordError::Capture)
+    }
+}
+
+/// Transport replaying a capture
+///
+/// Written bytes have to match the recorded `tx` records, otherwise writing fails with
+/// [ErrorKind::InvalidData]. Reads return the following `rx` records and fail with the
+/// kind of `rx-error` records. When the next record is a request (or the capture is exhausted)
+/// reads fail with [ErrorKind::TimedOut].
+/// Unread `rx` records are skipped when the next request is written.
+#[derive(Debug, Clone)]
+pub struct Replay<'a> {
+    records: Records<'a>,
+    current: Option<Current<'a>>,
+}
+
+/// Unconsumed part of the current record
+#[derive(Debug, Clone)]
+enum Current<'a> {
+    Tx(Unescape<'a>),
+    Rx(Unescape<'a>),
+    RxError(ErrorKind),
+}
+
+impl<'a> Replay<'a> {
+    /// Replay `capture`, fails if a record is invalid
+    pub fn new(capture: &'a [u8]) -> Result<Self, CaptureParseError> {
+        let records = Records::new(capture);
+        records.clone().try_for_each(|record| record.map(|_| ()))?;
+        Ok(Replay {
+            records,
+            current: None,
+        })
+    }
+    /// Whether all records were consumed
+    pub fn is_done(&mut self) -> bool {
+        self.peek().is_none()
+    }
+    /// The current record, skips consumed ones
+    fn peek(&mut self) -> Option<&mut Current<'a>> {
+        let consumed = match &self.current {
+            None => true,
+            Some(Current::Tx(data) | Current::Rx(data)) => data.is_empty(),
+            Some(Current::RxError(_)) => false,
+        };
+        if consumed {
+            self.current = self.next_record();
+            if let Some(Current::Tx(data) | Current::Rx(data)) = &self.current
+                && data.is_empty()
+            {
+                return self.peek();
+            }
+        }
+        self.current.as_mut()
+    }
+    fn next_record(&mut self) -> Option<Current<'a>> {
+        // Records were validated by `new`
+        let record = self.records.next()?.ok()?;
+        Some(match record.event {
+            Event::Tx(data) => Current::Tx(data.bytes()),
+            Event::Rx(data) => Current::Rx(data.bytes()),
+            Event::RxError(kind) => Current::RxError(kind),
+        })
+    }
+}
+
+impl ErrorType for Replay<'_> {
+    type Error = ErrorKind;
+}
+impl Read for Replay<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        match self.peek() {
+            Some(Current::Rx(data)) => {
+                let mut len = 0;
+                for (slot, byte) in buf.iter_mut().zip(data) {
+                    *slot = byte;
+                    len += 1;
+                }
+                Ok(len)
+            }
+            Some(Current::RxError(kind)) => {
+                let kind = *kind;
+                self.current = None;
+                Err(kind)
+            }
+            _ => Err(ErrorKind::TimedOut),
+        }
+    }
+}
+impl ReadReady for Replay<'_> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(matches!(self.peek(), Some(Current::Rx(_))))
+    }
+}
+impl Write for Replay<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        for &byte in buf {
+            loop {
+                match self.peek() {
+                    Some(Current::Tx(data)) => {
+                        let expected = data.next();
+                        if expected != Some(byte) {
+                            log::error!("Request doesn't match capture, expected {expected:?}");
+                            return Err(ErrorKind::InvalidData);
+                        }
+                        break;
+                    }
+                    Some(_) => {
+                        log::debug!("Skipping unread response of capture");
+                        self.current = None;
+                    }
+                    None => {
+                        log::error!("Request after end of capture");
+                        return Err(ErrorKind::InvalidData);
+                    }
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CaptureParseError, Event, Record, Recorder, Records, Replay};
+    use crate::{Error, MAX_UNENCODED_PAYLOAD_LEN, PylontechBms, commands::PackData};
+    use embedded_io::ErrorKind;
+
+    /// A successful and a timed out "_get analog value_" request
+    const CAPTURE: &[u8] = b"# pylon-lfp capture v1
+0 tx ~28014642E00201FD2D\\r
+84211 rx ~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\\r
+1000000 tx ~28014642E00201FD2D\\r
+2000000 rx-error TimedOut
+";
+
+    fn analog_values<T: embedded_io::Read + embedded_io::Write>(bms: &mut PylontechBms<T>) {
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let measurements = bms.get_analog_value(1, &mut buf).unwrap();
+        let pack: PackData<'_> = measurements.get_pack(0).unwrap();
+        assert_eq!(pack.pack_voltage.get_raw(), 50981);
+        assert!(matches!(
+            bms.get_analog_value(1, &mut buf),
+            Err(Error::Transport(e)) if embedded_io::Error::kind(&e) == ErrorKind::TimedOut
+        ));
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut clock = 0;
+        let recorder = Recorder::new(Replay::new(CAPTURE).unwrap(), Vec::new(), move || {
+            clock += 1000;
+            clock
+        });
+        let mut bms: PylontechBms<_> = PylontechBms::new(recorder);
+        analog_values(&mut bms);
+        let (replay, capture) = bms.into_inner().finish().unwrap();
+        assert!(replay.clone().is_done());
+
+        // Records are merged per direction, only timestamps differ
+        let recorded: Vec<Record<'_>> = Records::new(&capture).map(Result::unwrap).collect();
+        let original: Vec<Record<'_>> = Records::new(CAPTURE).map(Result::unwrap).collect();
+        assert_eq!(recorded.len(), original.len());
+        for (recorded, original) in recorded.iter().zip(&original) {
+            match (recorded.event, original.event) {
+                (Event::Tx(a), Event::Tx(b)) | (Event::Rx(a), Event::Rx(b)) => {
+                    assert!(a.bytes().eq(b.bytes()))
+                }
+                (a, b) => assert_eq!(a, b),
+            }
+        }
+        assert!(recorded.windows(2).all(|r| r[0].timestamp < r[1].timestamp));
+
+        let mut bms: PylontechBms<_> = PylontechBms::new(Replay::new(&capture).unwrap());
+        analog_values(&mut bms);
+        // Requests not in the capture fail
+        assert!(matches!(
+            bms.get_system_parameter(),
+            Err(Error::Transport(ErrorKind::InvalidData))
+        ));
+
+        assert_eq!(
+            Replay::new(b"# comment\n0 tx ok\n12 rx \\q\n").unwrap_err(),
+            CaptureParseError::InvalidData { line: 3 }
+        );
+        assert_eq!(
+            Replay::new(b"0 tx ok\n12 rx-error Unknown").unwrap_err(),
+            CaptureParseError::InvalidEvent { line: 2 }
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use zerocopy::FromBytes;
+
+use crate::dialect::ResponsePayload;
+use crate::types::{AlarmState, ChangeFlags};
+
+/// Errors encountered while parsing a [AlarmInfo]
+#[derive(Debug)]
+pub enum AlarmInfoParseError {
+    InvalidInput,
+}
+impl<T: embedded_io::Error> From<AlarmInfoParseError> for crate::Error<T> {
+    fn from(value: AlarmInfoParseError) -> Self {
+        match value {
+            AlarmInfoParseError::InvalidInput => crate::Error::InvalidInput,
+        }
+    }
+}
+
+/// Response payload of a "_get alarm info_" command
+///
+/// Contains the [AlarmState] of every measurement of a single pack,
+/// followed by a number of user-defined status bytes.
+/// The meaning of the status bytes depends on the BMS.
+#[derive(Debug)]
+pub struct AlarmInfo<'a> {
+    pub flags: ChangeFlags,
+    /// Address of the pack this response belongs to
+    pub pack_address: u8,
+    /// Alarm state for every cell voltage
+    pub cell_voltage_alarms: &'a [AlarmState],
+    /// Alarm state for every temperature
+    pub temperature_alarms: &'a [AlarmState],
+    pub charge_current_alarm: AlarmState,
+    pub pack_voltage_alarm: AlarmState,
+    pub discharge_current_alarm: AlarmState,
+    /// User-defined status bytes
+    status: &'a [u8],
+}
+
+impl<'a> AlarmInfo<'a> {
+    pub fn from_bytes(buf: &'a [u8]) -> Result<AlarmInfo<'a>, AlarmInfoParseError> {
+        let (flags, rest) =
+            ChangeFlags::read_from_prefix(buf).map_err(|_| AlarmInfoParseError::InvalidInput)?;
+        let (pack_address, rest) =
+            u8::read_from_prefix(rest).map_err(|_| AlarmInfoParseError::InvalidInput)?;
+
+        let (cell_count, rest) =
+            u8::read_from_prefix(rest).map_err(|_| AlarmInfoParseError::InvalidInput)?;
+        let (cell_voltage_alarms, rest) =
+            <[AlarmState]>::ref_from_prefix_with_elems(rest, cell_count as usize)
+                .map_err(|_| AlarmInfoParseError::InvalidInput)?;
+
+        let (temp_count, rest) =
+            u8::read_from_prefix(rest).map_err(|_| AlarmInfoParseError::InvalidInput)?;
+        let (temperature_alarms, rest) =
+            <[AlarmState]>::ref_from_prefix_with_elems(rest, temp_count as usize)
+                .map_err(|_| AlarmInfoParseError::InvalidInput)?;
+
+        let (charge_current_alarm, rest) =
+            AlarmState::read_from_prefix(rest).map_err(|_| AlarmInfoParseError::InvalidInput)?;
+        let (pack_voltage_alarm, rest) =
+            AlarmState::read_from_prefix(rest).map_err(|_| AlarmInfoParseError::InvalidInput)?;
+        let (discharge_current_alarm, rest) =
+            AlarmState::read_from_prefix(rest).map_err(|_| AlarmInfoParseError::InvalidInput)?;
+
+        let (status_count, rest) =
+            u8::read_from_prefix(rest).map_err(|_| AlarmInfoParseError::InvalidInput)?;
+        let status = rest
+            .get(..status_count as usize)
+            .ok_or(AlarmInfoParseError::InvalidInput)?;
+
+        Ok(AlarmInfo {
+            flags,
+            pack_address,
+            cell_voltage_alarms,
+            temperature_alarms,
+            charge_current_alarm,
+            pack_voltage_alarm,
+            discharge_current_alarm,
+            status,
+        })
+    }
+    /// Get the raw user-defined status bytes
+    pub fn status_bytes(&self) -> &'a [u8] {
+        self.status
+    }
+    /// Get a user-defined status byte by offset
+    pub fn status_u8(&self, offset: usize) -> Option<u8> {
+        self.status.get(offset).copied()
+    }
+    /// Get two user-defined status bytes (big endian) by offset
+    pub fn status_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.status.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+impl<'a> ResponsePayload<'a> for AlarmInfo<'a> {
+    type Error = AlarmInfoParseError;
+
+    fn from_payload(buf: &'a [u8]) -> Result<Self, Self::Error> {
+        AlarmInfo::from_bytes(buf)
+    }
+}
@@ -1,8 +1,9 @@
-use log::{error, trace};
-use zerocopy::FromBytes;
+use log::{error, trace, warn};
+use zerocopy::{FromBytes, IntoBytes, byteorder::big_endian};
 
+use crate::dialect::ResponsePayload;
 use crate::types::{
-    Ampere, AmpereHours, ChangeFlags, Temperature, Volt,
+    Ampere, AmpereHours, ChangeFlags, ScalingProfile, Temperature, Volt,
     exponents::{DECI, MILLI},
 };
 
@@ -19,9 +20,20 @@ impl<T: embedded_io::Error> From<AnalogValueParseError> for crate::Error<T> {
     }
 }
 
+/// Errors encountered while building a "_get analog value_" response payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalogValueBuildError {
+    /// The payload doesn't fit the buffer
+    BufferTooSmall,
+    /// More than 255 packs, cells, temperatures or user-defined fields
+    TooManyEntries,
+}
+
 /// Response payload of a "_get analog value_" command
 ///
 /// Containing flags and measurement data for one or multiple battery packs.
+/// Use [AnalogValueBuilder] to serialise a response payload.
+#[derive(Debug)]
 pub struct AnalogValueResponse<'a> {
     /// [PackData] buffer
     buf: &'a [u8],
@@ -55,11 +67,17 @@ pub struct PackData<
     /// `User-Defined` field
     ///
     /// This is specified to be always `2`. _(?!)_
+    ///
+    /// It holds the number of 16-bit fields following
+    /// ([PackData::total_capacity] and [PackData::cell_cycles]).
+    /// Vendor dialects report additional fields, see [PackData::user_defined_data].
     pub user_defined: u8,
     /// Total capacity of the pack
     pub total_capacity: AmpereHours<AMP_HOUR_EXP>,
     /// Cycles of the pack
     pub cell_cycles: u16,
+    /// Additional user-defined fields following the cycles
+    user_defined_data: &'a [u8],
     /// The length in bytes of this PackData
     len_bytes: usize,
 }
@@ -73,6 +91,42 @@ impl<
     const TEMP_EXP: i8,
 > PackData<'a, CELL_VOLTAGE_EXP, TOTAL_VOLTAGE_EXP, CURRENT_EXP, AMP_HOUR_EXP, TEMP_EXP>
 {
+    /// Create pack data following the specification (with `user_defined` of `2`)
+    ///
+    /// Serialise it with [AnalogValueBuilder::add_pack].
+    pub fn new(
+        cell_voltages: &'a [Volt<CELL_VOLTAGE_EXP>],
+        temperatures: &'a [Temperature<TEMP_EXP>],
+        pack_current: Ampere<CURRENT_EXP>,
+        pack_voltage: Volt<TOTAL_VOLTAGE_EXP>,
+        pack_remaining: AmpereHours<AMP_HOUR_EXP>,
+        total_capacity: AmpereHours<AMP_HOUR_EXP>,
+        cell_cycles: u16,
+    ) -> Self {
+        PackData {
+            cell_voltages,
+            temperatures,
+            pack_current,
+            pack_voltage,
+            pack_remaining,
+            user_defined: 2,
+            total_capacity,
+            cell_cycles,
+            user_defined_data: &[],
+            len_bytes: 13 + (cell_voltages.len() + temperatures.len()) * 2,
+        }
+    }
+    /// Set the additional user-defined fields (2 bytes each) following the cycles
+    ///
+    /// Replaces previously set fields and updates [PackData::user_defined] accordingly.
+    /// A trailing odd byte of `data` is ignored.
+    pub fn with_user_defined_data(mut self, data: &'a [u8]) -> Self {
+        let data = &data[..data.len() & !1];
+        self.len_bytes = self.len_bytes - self.user_defined_data.len() + data.len();
+        self.user_defined = (2 + data.len() / 2).min(u8::MAX as usize) as u8;
+        self.user_defined_data = data;
+        self
+    }
     fn from_bytes(buf: &'a [u8]) -> Result<Self, AnalogValueParseError> {
         if buf.is_empty() {
             return Err(AnalogValueParseError::InvalidInput);
@@ -111,9 +165,20 @@ impl<
             AmpereHours::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
 
         // Cell cycles
-        let cell_cycles_be = [rest[0], rest[1]];
-        let rest = &rest[2..];
-        let cell_cycles = u16::from_be_bytes(cell_cycles_be);
+        let (cell_cycles, rest) = big_endian::U16::read_from_prefix(rest)
+            .map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let cell_cycles = cell_cycles.get();
+
+        // Additional user-defined fields (2 bytes each),
+        // some packs report more fields than they send
+        let extra_len = user_defined.saturating_sub(2) as usize * 2;
+        if extra_len > rest.len() {
+            warn!(
+                "Pack reports {extra_len} bytes of user-defined data, only {} present",
+                rest.len()
+            );
+        }
+        let (user_defined_data, rest) = rest.split_at(extra_len.min(rest.len()));
 
         let len_bytes = buf.len() - rest.len();
 
@@ -126,13 +191,122 @@ impl<
             user_defined,
             total_capacity,
             cell_cycles,
+            user_defined_data,
             len_bytes,
         })
     }
     fn len(&self) -> usize {
         self.len_bytes
     }
+    /// Serialise into the start of `buf`, returns the number of bytes written
+    fn write_to(&self, buf: &mut [u8]) -> Result<usize, AnalogValueBuildError> {
+        let cell_count = u8::try_from(self.cell_voltages.len())
+            .map_err(|_| AnalogValueBuildError::TooManyEntries)?;
+        let temp_count = u8::try_from(self.temperatures.len())
+            .map_err(|_| AnalogValueBuildError::TooManyEntries)?;
+        if self.user_defined_data.len() / 2 > u8::MAX as usize - 2 {
+            return Err(AnalogValueBuildError::TooManyEntries);
+        }
+        let fields: [&[u8]; 10] = [
+            &[cell_count],
+            self.cell_voltages.as_bytes(),
+            &[temp_count],
+            self.temperatures.as_bytes(),
+            self.pack_current.as_bytes(),
+            self.pack_voltage.as_bytes(),
+            self.pack_remaining.as_bytes(),
+            &[self.user_defined],
+            self.total_capacity.as_bytes(),
+            &self.cell_cycles.to_be_bytes(),
+        ];
+        let mut len = 0;
+        for field in fields.into_iter().chain([self.user_defined_data]) {
+            buf.get_mut(len..len + field.len())
+                .ok_or(AnalogValueBuildError::BufferTooSmall)?
+                .copy_from_slice(field);
+            len += field.len();
+        }
+        Ok(len)
+    }
+    /// Raw data of additional user-defined fields
+    ///
+    /// Holds `2 * (user_defined - 2)` bytes reported after [PackData::cell_cycles].
+    /// Empty for packs following the specification,
+    /// shorter if the response ended early (see [PackData::user_defined_truncated]).
+    pub fn user_defined_data(&self) -> &'a [u8] {
+        self.user_defined_data
+    }
+    /// Whether the response ended before all fields counted by [PackData::user_defined]
+    pub fn user_defined_truncated(&self) -> bool {
+        self.user_defined_data.len() < self.user_defined.saturating_sub(2) as usize * 2
+    }
 }
+impl<'a> PackData<'a> {
+    /// Apply a runtime [ScalingProfile] to the measurements
+    pub fn scaled(self, profile: ScalingProfile) -> ScaledPackData<'a> {
+        ScaledPackData {
+            pack: self,
+            profile,
+        }
+    }
+}
+
+/// [PackData] scaled by a runtime [ScalingProfile]
+///
+/// Measurements are returned as floating-point values in SI units
+/// (Volt, Ampere, Ampere-hours and Kelvin).
+/// This can be obtained from [AnalogValueResponse::get_pack_scaled] or [PackData::scaled].
+#[derive(Debug)]
+pub struct ScaledPackData<'a> {
+    pack: PackData<'a>,
+    profile: ScalingProfile,
+}
+
+impl<'a> ScaledPackData<'a> {
+    /// The [ScalingProfile] applied
+    pub fn profile(&self) -> ScalingProfile {
+        self.profile
+    }
+    /// The unscaled [PackData]
+    pub fn raw(&self) -> &PackData<'a> {
+        &self.pack
+    }
+    /// Cell voltages in Volt
+    pub fn cell_voltages(&self) -> impl ExactSizeIterator<Item = f32> + '_ {
+        self.pack
+            .cell_voltages
+            .iter()
+            .map(|v| self.profile.cell_voltage(v))
+    }
+    /// Temperatures in Kelvin
+    pub fn temperatures(&self) -> impl ExactSizeIterator<Item = f32> + '_ {
+        self.pack
+            .temperatures
+            .iter()
+            .map(|t| self.profile.temperature(t))
+    }
+    /// Total pack current in Ampere
+    pub fn pack_current(&self) -> f32 {
+        self.profile.current(&self.pack.pack_current)
+    }
+    /// Total pack voltage in Volt
+    pub fn pack_voltage(&self) -> f32 {
+        self.profile.total_voltage(&self.pack.pack_voltage)
+    }
+    /// Remaining charge in Ampere-hours
+    pub fn pack_remaining(&self) -> f32 {
+        self.profile.amp_hours(&self.pack.pack_remaining)
+    }
+    /// Total capacity in Ampere-hours
+    pub fn total_capacity(&self) -> f32 {
+        self.profile.amp_hours(&self.pack.total_capacity)
+    }
+    /// Cycles of the pack
+    pub fn cell_cycles(&self) -> u16 {
+        self.pack.cell_cycles
+    }
+}
+
 impl<'a> AnalogValueResponse<'a> {
     pub fn from_bytes(buf: &'a [u8]) -> Result<AnalogValueResponse<'a>, AnalogValueParseError> {
         if buf.len() < 2 {
@@ -209,6 +383,107 @@ impl<'a> AnalogValueResponse<'a> {
         }
         PackData::from_bytes(rest)
     }
+    /// Get [PackData] by number, scaled by a runtime [ScalingProfile]
+    ///
+    /// Returns an error if the profile contains unsupported exponents.
+    pub fn get_pack_scaled(
+        &self,
+        pack_number: u8,
+        profile: ScalingProfile,
+    ) -> Result<ScaledPackData<'_>, AnalogValueParseError> {
+        if !profile.is_valid() {
+            error!("Scaling profile contains unsupported exponents: {profile:?}");
+            return Err(AnalogValueParseError::InvalidInput);
+        }
+        let pack: PackData<'_> = self.get_pack(pack_number)?;
+        Ok(pack.scaled(profile))
+    }
+}
+
+/// Serialises a "_get analog value_" response payload
+///
+/// Writes the flags and [PackData] of one or multiple packs into a buffer
+/// in the layout parsed by [AnalogValueResponse::from_bytes].
+///
+/// ```rust
+/// use pylon_lfp_protocol::{
+///     MAX_UNENCODED_PAYLOAD_LEN,
+///     commands::{AnalogValueBuilder, AnalogValueResponse, PackData},
+///     types::{Ampere, AmpereHours, ChangeFlags, Temperature, Volt},
+/// };
+///
+/// let cells = [Volt::new(3300); 16];
+/// let temperatures = [Temperature::new(2981); 4];
+/// let pack: PackData<'_> = PackData::new(
+///     &cells,
+///     &temperatures,
+///     Ampere::new(-1500),
+///     Volt::new(52800),
+///     AmpereHours::new(40000),
+///     AmpereHours::new(50000),
+///     12,
+/// );
+///
+/// let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+/// let mut builder = AnalogValueBuilder::new(&mut buf, ChangeFlags::new(false, false)).unwrap();
+/// builder.add_pack(&pack).unwrap();
+/// let payload = builder.finish();
+///
+/// let response = AnalogValueResponse::from_bytes(payload).unwrap();
+/// let parsed: PackData<'_> = response.get_pack(0).unwrap();
+/// assert_eq!(parsed.cell_cycles, 12);
+/// ```
+pub struct AnalogValueBuilder<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> AnalogValueBuilder<'b> {
+    /// Start a payload with `flags` and no packs in `buf`
+    pub fn new(buf: &'b mut [u8], flags: ChangeFlags) -> Result<Self, AnalogValueBuildError> {
+        let header = buf
+            .get_mut(..2)
+            .ok_or(AnalogValueBuildError::BufferTooSmall)?;
+        header.copy_from_slice(&[flags.as_bytes()[0], 0]);
+        Ok(AnalogValueBuilder { buf, len: 2 })
+    }
+    /// Append the [PackData] of the next pack
+    pub fn add_pack<
+        const CELL_VOLTAGE_EXP: i8,
+        const TOTAL_VOLTAGE_EXP: i8,
+        const CURRENT_EXP: i8,
+        const AMP_HOUR_EXP: i8,
+        const TEMP_EXP: i8,
+    >(
+        &mut self,
+        pack: &PackData<
+            '_,
+            CELL_VOLTAGE_EXP,
+            TOTAL_VOLTAGE_EXP,
+            CURRENT_EXP,
+            AMP_HOUR_EXP,
+            TEMP_EXP,
+        >,
+    ) -> Result<&mut Self, AnalogValueBuildError> {
+        if self.buf[1] == u8::MAX {
+            return Err(AnalogValueBuildError::TooManyEntries);
+        }
+        self.len += pack.write_to(&mut self.buf[self.len..])?;
+        self.buf[1] += 1;
+        Ok(self)
+    }
+    /// The serialised payload
+    pub fn finish(self) -> &'b [u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl<'a> ResponsePayload<'a> for AnalogValueResponse<'a> {
+    type Error = AnalogValueParseError;
+
+    fn from_payload(buf: &'a [u8]) -> Result<Self, Self::Error> {
+        AnalogValueResponse::from_bytes(buf)
+    }
 }
 
 #[cfg(test)]
@@ -217,6 +492,7 @@ mod tests {
         Frame,
         commands::{PackData, get_analog_value::AnalogValueResponse},
         frame::MAX_UNENCODED_PAYLOAD_LEN,
+        types::exponents::MILLI,
     };
     /// Get the payload from the response in the specification example
     fn payload_from_spec(info_buf: &mut [u8]) -> &[u8] {
@@ -238,6 +514,86 @@ mod tests {
         packet.info
     }
     #[test]
+    fn build_round_trip() {
+        use crate::{
+            commands::{AnalogValueBuilder, AnalogValueResponse},
+            types::{Ampere, ChangeFlags},
+        };
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let payload = payload_from_spec(&mut info_buf);
+        let response = AnalogValueResponse::from_bytes(payload).expect("Failed to parse payload");
+        let pack: PackData<'_> = response.get_pack(0).expect("Failed to parse PackData");
+
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut builder =
+            AnalogValueBuilder::new(&mut buf, response.flags).expect("Failed to start payload");
+        builder.add_pack(&pack).expect("Failed to add pack");
+        assert_eq!(builder.finish(), payload);
+
+        // Multiple packs with user-defined data
+        let extra = [0x12, 0x34, 0x56, 0x78];
+        let custom: PackData<'_> = PackData::new(
+            pack.cell_voltages,
+            pack.temperatures,
+            Ampere::new(-250),
+            pack.pack_voltage,
+            pack.pack_remaining,
+            pack.total_capacity,
+            7,
+        )
+        .with_user_defined_data(&extra);
+        let mut builder = AnalogValueBuilder::new(&mut buf, ChangeFlags::new(false, true))
+            .expect("Failed to start payload");
+        builder
+            .add_pack(&pack)
+            .and_then(|b| b.add_pack(&custom))
+            .expect("Failed to add packs");
+        let payload = builder.finish();
+
+        let response = AnalogValueResponse::from_bytes(payload).expect("Failed to parse payload");
+        assert!(!response.flags.switch_change());
+        assert!(response.flags.alarm_change());
+        assert_eq!(response.get_pack_count(), 2);
+        let parsed: PackData<'_> = response.get_pack(1).expect("Failed to parse PackData");
+        assert_eq!(parsed.cell_voltages.len(), 15);
+        assert_eq!(parsed.pack_current.get_raw(), -250);
+        assert_eq!(parsed.user_defined, 4);
+        assert_eq!(parsed.user_defined_data(), &extra);
+        assert_eq!(parsed.cell_cycles, 7);
+        assert_eq!(parsed.len(), custom.len());
+
+        // Replacing the user-defined data with less
+        let replaced = custom.with_user_defined_data(&extra[..2]);
+        assert_eq!(replaced.user_defined, 3);
+        assert_eq!(replaced.len(), parsed.len() - 2);
+        let replaced = replaced.with_user_defined_data(&[]);
+        assert_eq!(replaced.user_defined, 2);
+        assert_eq!(replaced.len(), pack.len());
+    }
+    #[test]
+    fn truncated_user_defined_data() {
+        // Capture of a 20 cell Superpack counting 2 fields more than it sends
+        const PACKET: &[u8] = b"~25014600D09A0001140D0A0D0A0D0B0D0B0D0B0D0B0D0C0D0B0D0B0D0A0D0C0D0C0D0C0D0C0D0C0D0C0D0C0D0C0D0C0D0C0A0B770B750B760B760B780B7A0B760B760B3C0B4000001A161EA504271000042710DBE5\r";
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut packet = PACKET;
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+        let response =
+            AnalogValueResponse::from_bytes(frame.info).expect("Failed to parse payload");
+        let pack: PackData<'_> = response.get_pack(0).expect("Failed to parse PackData");
+        assert_eq!(pack.cell_voltages.len(), 20);
+        assert_eq!(pack.cell_cycles, 4);
+        assert_eq!(pack.user_defined, 4);
+        assert_eq!(pack.user_defined_data(), [0x27, 0x10]);
+        assert!(pack.user_defined_truncated());
+
+        let payload = payload_from_spec(&mut info_buf);
+        let response = AnalogValueResponse::from_bytes(payload).expect("Failed to parse payload");
+        let pack: PackData<'_> = response.get_pack(0).expect("Failed to parse PackData");
+        assert!(!pack.user_defined_truncated());
+    }
+    #[test]
     fn parse_flags_and_pack_count() {
         let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
 
@@ -276,4 +632,63 @@ mod tests {
         assert_eq!(pack.total_capacity.get_raw(), 50000);
         assert_eq!(pack.cell_cycles, 2);
     }
+    #[test]
+    fn decode_scaled_packet_data() {
+        use crate::types::{ScalingProfile, exponents::CENTI};
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+
+        let payload = payload_from_spec(&mut info_buf);
+
+        let analog_value_response = AnalogValueResponse::from_bytes(payload)
+            .expect("Failed to parse analog value response from payload");
+
+        // Runtime scaling has to match the const generic exponents
+        let typed: PackData<'_> = analog_value_response
+            .get_pack(0)
+            .expect("Failed to parse PackData");
+        let pack = analog_value_response
+            .get_pack_scaled(0, ScalingProfile::SPECIFICATION)
+            .expect("Failed to parse PackData");
+        assert_eq!(pack.cell_voltages().len(), 15);
+        assert_eq!(
+            pack.cell_voltages().next(),
+            Some(typed.cell_voltages[0].get_volt())
+        );
+        assert_eq!(
+            pack.temperatures().next(),
+            Some(typed.temperatures[0].kelvin())
+        );
+        assert_eq!(pack.pack_voltage(), typed.pack_voltage.get_volt());
+        assert_eq!(
+            pack.pack_remaining(),
+            typed.pack_remaining.get_ampere_hours()
+        );
+        assert_eq!(
+            pack.total_capacity(),
+            typed.total_capacity.get_ampere_hours()
+        );
+
+        let typed: PackData<'_, MILLI, CENTI, CENTI, CENTI> = analog_value_response
+            .get_pack(0)
+            .expect("Failed to parse PackData");
+        let pack = analog_value_response
+            .get_pack_scaled(0, ScalingProfile::SUPERPACK)
+            .expect("Failed to parse PackData");
+        assert_eq!(
+            pack.cell_voltages().next(),
+            Some(typed.cell_voltages[0].get_volt())
+        );
+        assert_eq!(pack.pack_voltage(), typed.pack_voltage.get_volt());
+        assert_eq!(
+            pack.total_capacity(),
+            typed.total_capacity.get_ampere_hours()
+        );
+
+        let invalid = ScalingProfile {
+            current: 4,
+            ..ScalingProfile::SPECIFICATION
+        };
+        assert!(analog_value_response.get_pack_scaled(0, invalid).is_err());
+    }
 }
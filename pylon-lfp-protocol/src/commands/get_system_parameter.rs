@@ -1,8 +1,44 @@
 use core::fmt::Display;
 
-use crate::types::{Ampere, Temperature, Volt, exponents::MILLI};
+use crate::types::{Ampere, ScalingProfile, Temperature, Volt, exponents::MILLI};
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 
+/// Response payload of a "_get system parameter_" command
+///
+/// The struct has the layout of the `INFO` field,
+/// it is parsed with [FromBytes] and serialised with [IntoBytes]:
+///
+/// ```rust
+/// use pylon_lfp_protocol::{
+///     Frame, MAX_UNENCODED_PAYLOAD_LEN, ResponseCode, Version,
+///     commands::SystemParameter,
+///     types::{Ampere, Temperature, Volt},
+/// };
+/// use zerocopy::{FromBytes, IntoBytes};
+///
+/// let parameter: SystemParameter = SystemParameter {
+///     unit_cell_voltage: Volt::new(3300),
+///     unit_cell_low_voltage_threshold: Volt::new(3000),
+///     unit_cell_under_voltage_threshold: Volt::new(2800),
+///     charge_upper_limit_temp: Temperature::new(3181),
+///     charge_lower_limit_temp: Temperature::new(2731),
+///     charge_lower_limit_current: Ampere::new(-10000),
+///     upper_limit_total_voltage: Volt::new(54000),
+///     lower_limit_total_voltage: Volt::new(45000),
+///     under_voltage_of_total_voltage: Volt::new(44800),
+///     discharge_upper_limit_temp: Temperature::new(3331),
+///     discharge_lower_limit_temp: Temperature::new(2531),
+///     discharge_lower_limit_current: Ampere::new(-20000),
+/// };
+/// let response = Frame::new(Version::default(), 1, ResponseCode::Normal.into(), parameter.as_bytes());
+/// let mut encoded = Vec::new();
+/// response.encode(&mut encoded).unwrap();
+///
+/// let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+/// let frame = Frame::decode(&mut encoded.as_slice(), &mut info_buf).unwrap();
+/// let parsed: SystemParameter = SystemParameter::read_from_bytes(frame.info).unwrap();
+/// assert_eq!(parsed.upper_limit_total_voltage.get_raw(), 54000);
+/// ```
 #[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
 #[repr(C)]
 pub struct SystemParameter<
@@ -26,6 +62,55 @@ pub struct SystemParameter<
     pub discharge_lower_limit_current: Ampere<CURRENT_EXP>,
 }
 
+impl<
+    const CELL_VOLTAGE_EXP: i8,
+    const TOTAL_VOLTAGE_EXP: i8,
+    const CURRENT_EXP: i8,
+    const TEMP_EXP: i8,
+> SystemParameter<CELL_VOLTAGE_EXP, TOTAL_VOLTAGE_EXP, CURRENT_EXP, TEMP_EXP>
+{
+    /// Apply a runtime [ScalingProfile] to the parameters
+    pub fn scaled(&self, profile: ScalingProfile) -> ScaledSystemParameter {
+        ScaledSystemParameter {
+            unit_cell_voltage: profile.cell_voltage(&self.unit_cell_voltage),
+            unit_cell_low_voltage_threshold: profile
+                .cell_voltage(&self.unit_cell_low_voltage_threshold),
+            unit_cell_under_voltage_threshold: profile
+                .cell_voltage(&self.unit_cell_under_voltage_threshold),
+            charge_upper_limit_temp: profile.temperature(&self.charge_upper_limit_temp),
+            charge_lower_limit_temp: profile.temperature(&self.charge_lower_limit_temp),
+            charge_lower_limit_current: profile.current(&self.charge_lower_limit_current),
+            upper_limit_total_voltage: profile.total_voltage(&self.upper_limit_total_voltage),
+            lower_limit_total_voltage: profile.total_voltage(&self.lower_limit_total_voltage),
+            under_voltage_of_total_voltage: profile
+                .total_voltage(&self.under_voltage_of_total_voltage),
+            discharge_upper_limit_temp: profile.temperature(&self.discharge_upper_limit_temp),
+            discharge_lower_limit_temp: profile.temperature(&self.discharge_lower_limit_temp),
+            discharge_lower_limit_current: profile.current(&self.discharge_lower_limit_current),
+        }
+    }
+}
+
+/// [SystemParameter] scaled by a runtime [ScalingProfile]
+///
+/// Values are in SI units (Volt, Ampere and Kelvin).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaledSystemParameter {
+    pub unit_cell_voltage: f32,
+    pub unit_cell_low_voltage_threshold: f32,
+    /// Under voltage protection threshold
+    pub unit_cell_under_voltage_threshold: f32,
+    pub charge_upper_limit_temp: f32,
+    pub charge_lower_limit_temp: f32,
+    pub charge_lower_limit_current: f32,
+    pub upper_limit_total_voltage: f32,
+    pub lower_limit_total_voltage: f32,
+    pub under_voltage_of_total_voltage: f32,
+    pub discharge_upper_limit_temp: f32,
+    pub discharge_lower_limit_temp: f32,
+    pub discharge_lower_limit_current: f32,
+}
+
 impl Display for SystemParameter {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         writeln!(f, "Cell voltage: {}", self.unit_cell_voltage)?;
@@ -1,7 +1,11 @@
 //! Data types associated with supported commands
 
+mod get_alarm_info;
 mod get_analog_value;
 mod get_system_parameter;
+mod request;
 
+pub use get_alarm_info::*;
 pub use get_analog_value::*;
 pub use get_system_parameter::*;
+pub use request::*;
@@ -0,0 +1,164 @@
+use crate::{Cid2, CommandCode, Frame};
+
+/// Errors encountered while parsing a [Request]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestParseError {
+    /// The frame is a response, not a command
+    NotACommand,
+    /// The `INFO` field doesn't match the command
+    InvalidInput,
+}
+impl<T: embedded_io::Error> From<RequestParseError> for crate::Error<T> {
+    fn from(value: RequestParseError) -> Self {
+        match value {
+            RequestParseError::NotACommand => crate::Error::UnsupportedControlIdentifier,
+            RequestParseError::InvalidInput => crate::Error::InvalidInput,
+        }
+    }
+}
+
+/// A command sent by a master (e.g. an inverter) with its typed `INFO` field
+///
+/// Parsed from frames decoded with [Frame::decode_command].
+///
+/// ```rust
+/// use pylon_lfp_protocol::{Frame, MAX_UNENCODED_PAYLOAD_LEN, commands::Request};
+///
+/// let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+/// let mut packet: &[u8] = b"~28014642E00201FD2D\r";
+/// let frame = Frame::decode_command(&mut packet, &mut info_buf).unwrap();
+/// assert_eq!(
+///     Request::from_frame(&frame),
+///     Ok(Request::GetAnalogValue { pack_address: 1 })
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Request<'a> {
+    /// Get protocol version
+    GetProtocolVersion,
+    /// Get manufacturer info
+    GetManufacturerInfo,
+    /// Get system parameter
+    GetSystemParameter,
+    /// Get analog values of the pack at `pack_address` (`0xFF` for all packs)
+    GetAnalogValue { pack_address: u8 },
+    /// Get alarm info of the pack at `pack_address` (`0xFF` for all packs)
+    GetAlarmInfo { pack_address: u8 },
+    /// Get charge / discharge management info of the pack at `pack_address`
+    GetCharge { pack_address: u8 },
+    /// Get serial number of the pack at `pack_address`
+    GetSerialNumber { pack_address: u8 },
+    /// Command with a `INFO` field not interpreted by this crate
+    Other {
+        command: CommandCode,
+        info: &'a [u8],
+    },
+}
+
+impl<'a> Request<'a> {
+    /// Parse the command and `INFO` field of a command frame
+    ///
+    /// The `INFO` field of commands without parameters is ignored,
+    /// some masters send a pack address with every command.
+    pub fn from_frame(frame: &Frame<'a>) -> Result<Request<'a>, RequestParseError> {
+        let Cid2::Command(command) = frame.cid2 else {
+            return Err(RequestParseError::NotACommand);
+        };
+        Self::from_info(command, frame.info)
+    }
+    /// Parse the (unencoded) `INFO` field of `command`
+    ///
+    /// See [Request::from_frame].
+    pub fn from_info(
+        command: CommandCode,
+        info: &'a [u8],
+    ) -> Result<Request<'a>, RequestParseError> {
+        let pack_address = || match info {
+            [pack_address] => Ok(*pack_address),
+            _ => Err(RequestParseError::InvalidInput),
+        };
+        let request = match command {
+            CommandCode::GetProtocolVersion => Request::GetProtocolVersion,
+            CommandCode::GetManufacturerInfo => Request::GetManufacturerInfo,
+            CommandCode::GetSystemParameter => Request::GetSystemParameter,
+            CommandCode::GetAnalogValue => Request::GetAnalogValue {
+                pack_address: pack_address()?,
+            },
+            CommandCode::GetAlarmInfo => Request::GetAlarmInfo {
+                pack_address: pack_address()?,
+            },
+            CommandCode::GetCharge => Request::GetCharge {
+                pack_address: pack_address()?,
+            },
+            CommandCode::GetSerialNumber => Request::GetSerialNumber {
+                pack_address: pack_address()?,
+            },
+            command => Request::Other { command, info },
+        };
+        Ok(request)
+    }
+    /// Command code of the request
+    pub fn command(&self) -> CommandCode {
+        match self {
+            Request::GetProtocolVersion => CommandCode::GetProtocolVersion,
+            Request::GetManufacturerInfo => CommandCode::GetManufacturerInfo,
+            Request::GetSystemParameter => CommandCode::GetSystemParameter,
+            Request::GetAnalogValue { .. } => CommandCode::GetAnalogValue,
+            Request::GetAlarmInfo { .. } => CommandCode::GetAlarmInfo,
+            Request::GetCharge { .. } => CommandCode::GetCharge,
+            Request::GetSerialNumber { .. } => CommandCode::GetSerialNumber,
+            Request::Other { command, .. } => *command,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Request, RequestParseError};
+    use crate::{Cid2, CommandCode, Error, Frame, MAX_UNENCODED_PAYLOAD_LEN};
+
+    #[test]
+    fn decode_requests() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let cases: [(&[u8], Request<'_>); 3] = [
+            (
+                b"~28014642E00201FD2D\r",
+                Request::GetAnalogValue { pack_address: 1 },
+            ),
+            (b"~2801464F0000FD91\r", Request::GetProtocolVersion),
+            (
+                b"~28014691E00202FD28\r",
+                Request::Other {
+                    command: CommandCode::SetCommunicationRate,
+                    info: &[2],
+                },
+            ),
+        ];
+        for (mut packet, expected) in cases {
+            let frame =
+                Frame::decode_command(&mut packet, &mut info_buf).expect("Error decoding packet");
+            assert_eq!(Request::from_frame(&frame), Ok(expected));
+            assert_eq!(frame.cid2, Cid2::Command(expected.command()));
+        }
+    }
+    #[test]
+    fn reject_responses() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        // Command frames aren't valid responses and vice versa
+        let mut packet: &[u8] = b"~28014642E00201FD2D\r";
+        let response = Frame::decode(&mut packet, &mut info_buf);
+        assert!(matches!(response, Err(Error::UnsupportedControlIdentifier)));
+
+        let mut packet: &[u8] = b"~280146000000FDAB\r";
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+        assert_eq!(
+            Request::from_frame(&frame),
+            Err(RequestParseError::NotACommand)
+        );
+        assert_eq!(
+            Request::from_info(CommandCode::GetAnalogValue, &[]),
+            Err(RequestParseError::InvalidInput)
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Heuristic detection of the [ScalingProfile] of a BMS
+//!
+//! Not every BMS transmits measurements in the exponents defined by the specification
+//! (see [types](crate::types)). Decoding with the wrong profile doesn't fail,
+//! it results in voltages and capacities off by a power of ten.
+//!
+//! [detect_scaling] decodes a "_get analog value_" response with every candidate profile
+//! and scores how plausible the result is:
+//!
+//! - the sum of the cell voltages has to match the pack voltage
+//! - capacities have to be in a plausible range and the remaining capacity
+//!   can't exceed the total capacity
+//! - the current can't exceed a few times the capacity (C-rate)
+//! - if [SystemParameter]s are available, the total voltage limits have to match
+//!   the cell voltage per cell
+//!
+//! ```rust, no_run
+//! # use pylon_lfp_protocol::{commands::AnalogValueResponse, detect::detect_scaling};
+//! # fn detect_example(payload: &[u8]) {
+//! let response = AnalogValueResponse::from_bytes(payload).unwrap();
+//! if let Some(detection) = detect_scaling(&response, None) {
+//!     if detection.confidence > 0.5 {
+//!         println!("BMS uses {:?}", detection.profile);
+//!     }
+//! }
+//! # }
+//! ```
+
+use log::trace;
+
+use crate::{
+    commands::{AnalogValueResponse, SystemParameter},
+    types::ScalingProfile,
+};
+
+/// Profiles considered by [detect_scaling]
+pub const CANDIDATES: [ScalingProfile; 2] =
+    [ScalingProfile::SPECIFICATION, ScalingProfile::SUPERPACK];
+
+/// Relative deviation of the cell voltage sum from the pack voltage scored as implausible
+const MAX_VOLTAGE_DEVIATION: f32 = 0.1;
+/// Plausible range of the total capacity of a pack in Ampere-hours
+const CAPACITY_RANGE: (f32, f32) = (1., 1000.);
+/// Highest plausible current relative to the total capacity
+const MAX_C_RATE: f32 = 3.;
+/// Plausible range of the total voltage limits per cell relative to the nominal cell voltage
+const LIMIT_PER_CELL_RANGE: (f32, f32) = (0.6, 1.4);
+/// Score of a failed check that doesn't rule a profile out
+const IMPLAUSIBLE: f32 = 0.25;
+
+/// Result of [detect_scaling]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// Most plausible profile
+    pub profile: ScalingProfile,
+    /// Plausibility of [Detection::profile], from `0.0` to `1.0`
+    pub score: f32,
+    /// How clearly [Detection::profile] beats the other candidates, from `0.0` to `1.0`
+    ///
+    /// This is the difference between the scores of the best and the second best candidate.
+    /// A low confidence means the measurements can't tell the candidates apart
+    /// (e.g. an empty response) or none of them is plausible.
+    pub confidence: f32,
+}
+
+/// Infer the [ScalingProfile] among [CANDIDATES] from measurements
+///
+/// `system_parameter` has to be decoded from the same BMS, it is scaled with the same profile.
+/// Returns [None] if no pack of `response` could be parsed.
+pub fn detect_scaling(
+    response: &AnalogValueResponse<'_>,
+    system_parameter: Option<&SystemParameter>,
+) -> Option<Detection> {
+    detect_scaling_among(&CANDIDATES, response, system_parameter)
+}
+
+/// Infer the most plausible of `candidates` from measurements
+///
+/// Candidates with unsupported exponents are skipped.
+/// See [detect_scaling].
+pub fn detect_scaling_among(
+    candidates: &[ScalingProfile],
+    response: &AnalogValueResponse<'_>,
+    system_parameter: Option<&SystemParameter>,
+) -> Option<Detection> {
+    let mut best: Option<(ScalingProfile, f32)> = None;
+    let mut runner_up = 0f32;
+    for &profile in candidates {
+        let Some(score) = score(profile, response, system_parameter) else {
+            continue;
+        };
+        match best {
+            Some((_, best_score)) if score <= best_score => runner_up = runner_up.max(score),
+            _ => {
+                if let Some((_, best_score)) = best {
+                    runner_up = runner_up.max(best_score);
+                }
+                best = Some((profile, score));
+            }
+        }
+    }
+    let (profile, score) = best?;
+    trace!("Detected {profile:?} with score {score}, runner-up {runner_up}");
+    Some(Detection {
+        profile,
+        score,
+        confidence: score - runner_up,
+    })
+}
+
+/// Plausibility of the measurements decoded with `profile`
+///
+/// The mean of the scores of all packs, [None] if no pack could be parsed.
+fn score(
+    profile: ScalingProfile,
+    response: &AnalogValueResponse<'_>,
+    system_parameter: Option<&SystemParameter>,
+) -> Option<f32> {
+    let mut total = 0f32;
+    let mut packs = 0u8;
+    let mut cell_count = 0;
+    for i in 0..response.get_pack_count() {
+        let Ok(pack) = response.get_pack_scaled(i, profile) else {
+            break;
+        };
+        cell_count = pack.cell_voltages().len();
+
+        let mut pack_score = 1f32;
+        if cell_count > 0 {
+            let cell_sum: f32 = pack.cell_voltages().sum();
+            let deviation = (cell_sum - pack.pack_voltage()).abs() / cell_sum.max(f32::EPSILON);
+            pack_score *= (1. - deviation / MAX_VOLTAGE_DEVIATION).max(0.);
+        }
+        let capacity = pack.total_capacity();
+        if !(CAPACITY_RANGE.0..=CAPACITY_RANGE.1).contains(&capacity) {
+            pack_score *= IMPLAUSIBLE;
+        }
+        if pack.pack_remaining() > capacity {
+            pack_score *= IMPLAUSIBLE;
+        }
+        if pack.pack_current().abs() > capacity * MAX_C_RATE {
+            pack_score *= IMPLAUSIBLE;
+        }
+        total += pack_score;
+        packs += 1;
+    }
+    if packs == 0 {
+        return None;
+    }
+    let mut score = total / packs as f32;
+
+    if let Some(parameter) = system_parameter
+        && cell_count > 0
+    {
+        let parameter = parameter.scaled(profile);
+        let nominal = parameter.unit_cell_voltage * cell_count as f32;
+        if nominal > 0. {
+            for limit in [
+                parameter.upper_limit_total_voltage,
+                parameter.lower_limit_total_voltage,
+            ] {
+                if !(LIMIT_PER_CELL_RANGE.0..=LIMIT_PER_CELL_RANGE.1).contains(&(limit / nominal)) {
+                    score *= IMPLAUSIBLE;
+                }
+            }
+        }
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detect_scaling;
+    use crate::{
+        Frame, MAX_UNENCODED_PAYLOAD_LEN, commands::AnalogValueResponse, types::ScalingProfile,
+    };
+
+    /// Analog value response of the specification example (15 cells, 50 Ah)
+    const SPECIFICATION_PACKET: &[u8] = b"~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\r";
+    /// Analog value response of a 20 cell 100 Ah Superpack
+    const SUPERPACK_PACKET: &[u8] = b"~25014600D09A0001140D0A0D0A0D0B0D0B0D0B0D0B0D0C0D0B0D0B0D0A0D0C0D0C0D0C0D0C0D0C0D0C0D0C0D0C0D0C0D0C0A0B770B750B760B760B780B7A0B760B760B3C0B4000001A161EA504271000042710DBE5\r";
+
+    #[test]
+    fn detect_specification() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut packet = SPECIFICATION_PACKET;
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+        let response = AnalogValueResponse::from_bytes(frame.info).expect("Failed to parse");
+
+        let detection = detect_scaling(&response, None).expect("No profile detected");
+        assert_eq!(detection.profile, ScalingProfile::SPECIFICATION);
+        assert!(detection.confidence > 0.5);
+    }
+    #[test]
+    fn detect_superpack() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut packet = SUPERPACK_PACKET;
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+        let response = AnalogValueResponse::from_bytes(frame.info).expect("Failed to parse");
+
+        let detection = detect_scaling(&response, None).expect("No profile detected");
+        assert_eq!(detection.profile, ScalingProfile::SUPERPACK);
+        assert!(detection.confidence > 0.5);
+    }
+}
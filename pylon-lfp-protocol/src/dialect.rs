@@ -0,0 +1,100 @@
+//! Vendor variants of the protocol
+//!
+//! Many batteries advertised as "_Pylontech compatible_" reuse the frame format
+//! of the Pylontech protocol but differ in `CID1`, protocol version,
+//! supported commands, scaling of measurements or payload layouts.
+//! A [Dialect] describes these differences, [PylontechBms](crate::PylontechBms)
+//! is generic over it.
+//!
+//! Dialects for further batteries can be implemented outside of this crate:
+//!
+//! ```rust
+//! use pylon_lfp_protocol::{
+//!     Cid1, CommandCode, PylontechBms, Version,
+//!     commands::{AlarmInfo, AnalogValueResponse},
+//!     dialect::Dialect,
+//!     types::ScalingProfile,
+//! };
+//!
+//! struct MyBattery;
+//! impl Dialect for MyBattery {
+//!     const CID1: Cid1 = Cid1::BATTERY_DATA;
+//!     const VERSION: Version = Version::new(2, 6);
+//!     const SCALING: ScalingProfile = ScalingProfile::SPECIFICATION;
+//!     type AnalogValue<'a> = AnalogValueResponse<'a>;
+//!     type AlarmInfo<'a> = AlarmInfo<'a>;
+//!
+//!     fn supports(command: CommandCode) -> bool {
+//!         matches!(command, CommandCode::GetAnalogValue | CommandCode::GetAlarmInfo)
+//!     }
+//! }
+//!
+//! # fn open<U: embedded_io::Read + embedded_io::Write>(uart: U) {
+//! let bms: PylontechBms<_, MyBattery> = PylontechBms::new(uart);
+//! # }
+//! ```
+
+use core::fmt::Debug;
+
+use crate::{
+    Cid1, CommandCode, Version,
+    commands::{AlarmInfo, AnalogValueResponse},
+    types::ScalingProfile,
+};
+
+/// Response data that can be parsed from the (unencoded) `INFO` field of a frame
+pub trait ResponsePayload<'a>: Sized {
+    type Error: Debug;
+
+    fn from_payload(buf: &'a [u8]) -> Result<Self, Self::Error>;
+}
+
+/// A vendor variant of the protocol
+pub trait Dialect {
+    /// `CID1` used in command and response frames
+    const CID1: Cid1;
+    /// Protocol version sent with commands
+    const VERSION: Version;
+    /// Exponents measurements are transmitted in
+    const SCALING: ScalingProfile;
+
+    /// Response payload of "_get analog value_"
+    type AnalogValue<'a>: ResponsePayload<'a>;
+    /// Response payload of "_get alarm info_"
+    type AlarmInfo<'a>: ResponsePayload<'a>;
+
+    /// Whether the BMS implements `command`
+    fn supports(command: CommandCode) -> bool;
+
+    /// `ADR` of a frame carrying a command for the pack at `pack_address`
+    ///
+    /// Defaults to the pack address.
+    fn frame_address(pack_address: u8) -> u8 {
+        pack_address
+    }
+}
+
+/// Pylontech RS232 protocol as specified
+pub struct Pylontech;
+
+impl Dialect for Pylontech {
+    const CID1: Cid1 = Cid1::BATTERY_DATA;
+    const VERSION: Version = Version::new(
+        crate::RS232_PROTOCOL_VERSION_MAJOR,
+        crate::RS232_PROTOCOL_VERSION_MINOR,
+    );
+    const SCALING: ScalingProfile = ScalingProfile::SPECIFICATION;
+
+    type AnalogValue<'a> = AnalogValueResponse<'a>;
+    type AlarmInfo<'a> = AlarmInfo<'a>;
+
+    fn supports(_command: CommandCode) -> bool {
+        true
+    }
+
+    /// Commands are sent to the master pack (address `1`),
+    /// the pack address is part of the command info.
+    fn frame_address(_pack_address: u8) -> u8 {
+        1
+    }
+}
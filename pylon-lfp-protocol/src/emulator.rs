@@ -0,0 +1,856 @@
+//! Emulated BMS answering requests (requires the `emulator` feature)
+//!
+//! An [Emulator] implements the BMS side of the protocol on any [embedded_io] transport.
+//! It decodes command frames sent by a master (e.g. an inverter or [PylontechBms](crate::PylontechBms))
+//! and answers them from a stack of [SimulatedPack]s.
+//!
+//! The packs model current flow, drifting state of charge and the spread of cell voltages
+//! over time, see [SimulatedPack::advance].
+//! Faults can be injected to test how a master handles misbehaving batteries (see [Fault]).
+//!
+//! ```rust, no_run
+//! use core::time::Duration;
+//! use pylon_lfp_protocol::emulator::{Emulator, PackConfig, SimulatedPack};
+//!
+//! # fn emulate<U: embedded_io::Read + embedded_io::Write>(uart: U) {
+//! let mut packs = [
+//!     SimulatedPack::new(PackConfig::default(), 0),
+//!     SimulatedPack::new(PackConfig::default(), 1),
+//! ];
+//! let mut emulator = Emulator::new(uart, &mut packs);
+//! loop {
+//!     if let Err(e) = emulator.serve() {
+//!         println!("Failed to serve request: {e:?}");
+//!     }
+//!     emulator.advance(Duration::from_millis(100));
+//! }
+//! # }
+//! ```
+
+use core::time::Duration;
+
+use embedded_io::{Read, Write};
+use log::{debug, warn};
+
+use crate::{
+    Cid1, Cid2, CommandCode, DecodeOptions, Error, Frame, MAX_UNENCODED_PAYLOAD_LEN, ResponseCode,
+    Version,
+    commands::{AnalogValueBuilder, PackData, Request, SystemParameter},
+    dialect::Dialect,
+    types::{
+        AlarmState, Ampere, AmpereHours, ChangeFlags, ScalingProfile, Temperature, Volt,
+        exponents::number,
+    },
+};
+use zerocopy::IntoBytes;
+
+/// Maximum number of cells of a [SimulatedPack]
+pub const MAX_CELLS: usize = 32;
+/// Maximum number of temperature sensors of a [SimulatedPack]
+pub const MAX_TEMPERATURES: usize = 16;
+
+/// The state of charge a discharging pack switches to charging at
+const MIN_SOC: f32 = 0.1;
+/// Internal resistance of a cell in Ohm for a 50 Ah pack
+const CELL_RESISTANCE: f32 = 0.0005;
+/// Time constant of the pack temperature in seconds
+const THERMAL_TIME_CONSTANT: f32 = 600.;
+/// Temperature rise per squared Ampere
+const HEATING: f32 = 0.002;
+/// Open-circuit voltage of a LFP cell by state of charge
+const OCV: [(f32, f32); 8] = [
+    (0.0, 2.80),
+    (0.05, 3.10),
+    (0.1, 3.20),
+    (0.3, 3.26),
+    (0.6, 3.29),
+    (0.9, 3.33),
+    (0.97, 3.40),
+    (1.0, 3.55),
+];
+
+/// Configuration of a [SimulatedPack]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PackConfig {
+    /// Number of cells in series, at most [MAX_CELLS]
+    pub cells: u8,
+    /// Number of temperature sensors, at most [MAX_TEMPERATURES]
+    pub temperatures: u8,
+    /// Total capacity in Ampere-hours
+    pub capacity: f32,
+    /// Current in Ampere, positive while charging
+    ///
+    /// The direction is reversed when the pack is full or nearly empty.
+    pub current: f32,
+    /// State of charge at start, from `0.0` to `1.0`
+    pub initial_soc: f32,
+    /// Difference between the lowest and the highest cell voltage at half charge in Volt
+    ///
+    /// The spread widens towards full and empty.
+    pub cell_spread: f32,
+    /// Temperature of the environment in Kelvin
+    pub ambient_temperature: f32,
+    /// Cycles at start
+    pub cycles: u16,
+}
+impl Default for PackConfig {
+    fn default() -> Self {
+        PackConfig {
+            cells: 15,
+            temperatures: 5,
+            capacity: 50.,
+            current: -10.,
+            initial_soc: 0.8,
+            cell_spread: 0.01,
+            ambient_temperature: 298.15,
+            cycles: 0,
+        }
+    }
+}
+
+/// A simulated battery pack
+#[derive(Debug, Clone)]
+pub struct SimulatedPack {
+    config: PackConfig,
+    /// State of charge from `0.0` to `1.0`
+    soc: f32,
+    /// Current in Ampere
+    current: f32,
+    /// Share of the current flowing through this pack, makes the SoC of packs drift apart
+    current_share: f32,
+    /// Pack temperature in Kelvin
+    temperature: f32,
+    cycles: u16,
+    /// Ampere-hours discharged since the last cycle was counted
+    discharged: f32,
+    /// Deviation of each cell from the mean voltage at half charge
+    cell_offsets: [f32; MAX_CELLS],
+    /// Deviation of each temperature sensor from the pack temperature
+    sensor_offsets: [f32; MAX_TEMPERATURES],
+}
+
+impl SimulatedPack {
+    /// Create a pack from `config`
+    ///
+    /// `seed` determines the spread of the cells and sensors,
+    /// use a different one for every pack of a stack.
+    pub fn new(config: PackConfig, seed: u32) -> Self {
+        let mut config = config;
+        config.cells = config.cells.min(MAX_CELLS as u8);
+        config.temperatures = config.temperatures.min(MAX_TEMPERATURES as u8);
+
+        let mut random = Random::new(seed);
+        let cell_offsets = core::array::from_fn(|_| random.next() * config.cell_spread / 2.);
+        let sensor_offsets = core::array::from_fn(|_| random.next() * 0.5);
+        SimulatedPack {
+            config,
+            soc: config.initial_soc.clamp(0., 1.),
+            current: config.current,
+            current_share: 1. + random.next() * 0.02,
+            temperature: config.ambient_temperature,
+            cycles: config.cycles,
+            discharged: 0.,
+            cell_offsets,
+            sensor_offsets,
+        }
+    }
+    /// The configuration of the pack
+    pub fn config(&self) -> &PackConfig {
+        &self.config
+    }
+    /// State of charge from `0.0` to `1.0`
+    pub fn soc(&self) -> f32 {
+        self.soc
+    }
+    /// Set the state of charge
+    pub fn set_soc(&mut self, soc: f32) {
+        self.soc = soc.clamp(0., 1.);
+    }
+    /// Current in Ampere, positive while charging
+    pub fn current(&self) -> f32 {
+        self.current * self.current_share
+    }
+    /// Set the current in Ampere, positive charges the pack
+    pub fn set_current(&mut self, current: f32) {
+        self.current = current;
+    }
+    /// Cell voltages in Volt
+    pub fn cell_voltages(&self) -> impl ExactSizeIterator<Item = f32> + '_ {
+        let ocv = open_circuit_voltage(self.soc);
+        // The spread widens towards full and empty
+        let spread = 1. + 2. * (2. * self.soc - 1.).abs();
+        let drop = self.current() * CELL_RESISTANCE * 50. / self.config.capacity.max(1.);
+        self.cell_offsets[..self.config.cells as usize]
+            .iter()
+            .map(move |offset| ocv + offset * spread + drop)
+    }
+    /// Pack voltage in Volt
+    pub fn pack_voltage(&self) -> f32 {
+        self.cell_voltages().sum()
+    }
+    /// Temperatures in Kelvin
+    pub fn temperatures(&self) -> impl ExactSizeIterator<Item = f32> + '_ {
+        self.sensor_offsets[..self.config.temperatures as usize]
+            .iter()
+            .map(|offset| self.temperature + offset)
+    }
+    /// Remaining charge in Ampere-hours
+    pub fn remaining(&self) -> f32 {
+        self.soc * self.config.capacity
+    }
+    /// Cycles of the pack
+    pub fn cycles(&self) -> u16 {
+        self.cycles
+    }
+    /// Let `dt` pass
+    ///
+    /// Updates the state of charge by the current, reverses the current when the pack
+    /// is full or nearly empty, counts cycles and heats the pack by the current.
+    pub fn advance(&mut self, dt: Duration) {
+        let seconds = dt.as_secs_f32();
+        let current = self.current();
+        let charge = current * seconds / 3600.;
+        self.soc = (self.soc + charge / self.config.capacity.max(f32::EPSILON)).clamp(0., 1.);
+        if charge < 0. {
+            self.discharged -= charge;
+            if self.discharged >= self.config.capacity {
+                self.discharged -= self.config.capacity;
+                self.cycles = self.cycles.saturating_add(1);
+            }
+        }
+        if (self.soc >= 1. && self.current > 0.) || (self.soc <= MIN_SOC && self.current < 0.) {
+            debug!("Reversing current at SoC {}", self.soc);
+            self.current = -self.current;
+        }
+
+        let target = self.config.ambient_temperature + HEATING * current * current;
+        let factor = (seconds / THERMAL_TIME_CONSTANT).min(1.);
+        self.temperature += (target - self.temperature) * factor;
+    }
+}
+
+/// A fault injected into responses of an [Emulator]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Don't respond at all
+    NoResponse,
+    /// Respond with a wrong `CHKSUM`
+    BadChecksum,
+    /// Respond with a error code (and empty `INFO`)
+    Response(ResponseCode),
+}
+
+/// Emulated BMS of a stack of [SimulatedPack]s
+///
+/// The packs have consecutive addresses starting at [Emulator::address] (default `1`).
+/// The emulator responds to command frames addressed to any of them or to `0xFF`
+/// and ignores all other frames.
+pub struct Emulator<'p, U: Read + Write> {
+    uart: U,
+    packs: &'p mut [SimulatedPack],
+    version: Version,
+    cid1: Cid1,
+    scaling: ScalingProfile,
+    address: u8,
+    options: DecodeOptions,
+    /// Fault injected into every n-th response
+    fault: Option<(Fault, u32)>,
+    /// Number of responses sent
+    responses: u32,
+}
+
+impl<'p, U: Read + Write> Emulator<'p, U> {
+    /// Create an emulator of `packs` answering requests on `uart`
+    pub fn new(uart: U, packs: &'p mut [SimulatedPack]) -> Self {
+        Emulator {
+            uart,
+            packs,
+            version: Version::default(),
+            cid1: Cid1::BATTERY_DATA,
+            scaling: ScalingProfile::SPECIFICATION,
+            address: 1,
+            options: DecodeOptions {
+                resync: true,
+                ..DecodeOptions::STRICT
+            },
+            fault: None,
+            responses: 0,
+        }
+    }
+    /// Set the protocol version reported and sent with responses
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+    /// Set the `CID1` requests have to carry
+    pub fn cid1(mut self, cid1: Cid1) -> Self {
+        self.cid1 = cid1;
+        self
+    }
+    /// Speak the `VER`, `CID1` and [Dialect::SCALING] of `D`
+    pub fn dialect<D: Dialect>(self) -> Self {
+        self.version(D::VERSION).cid1(D::CID1).scaling(D::SCALING)
+    }
+    /// Set the exponents measurements are transmitted in
+    ///
+    /// Profiles only hold supported exponents (see [ScalingProfile::new]).
+    pub fn scaling(mut self, scaling: ScalingProfile) -> Self {
+        self.scaling = scaling;
+        self
+    }
+    /// Set the address of the first pack
+    pub fn address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+    /// Set the [DecodeOptions] used for requests
+    ///
+    /// Defaults to [DecodeOptions::resync].
+    pub fn decode_options(mut self, options: DecodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Inject `fault` into every `every`-th response, disabled with [None]
+    pub fn inject_fault(&mut self, fault: Option<(Fault, u32)>) {
+        self.fault = fault.filter(|(_, every)| *every > 0);
+    }
+    /// The simulated packs
+    pub fn packs(&self) -> &[SimulatedPack] {
+        self.packs
+    }
+    /// The simulated packs, e.g. to change their current
+    pub fn packs_mut(&mut self) -> &mut [SimulatedPack] {
+        self.packs
+    }
+    /// Let `dt` pass for all packs, see [SimulatedPack::advance]
+    pub fn advance(&mut self, dt: Duration) {
+        for pack in self.packs.iter_mut() {
+            pack.advance(dt);
+        }
+    }
+    /// Release the transport
+    pub fn into_inner(self) -> U {
+        self.uart
+    }
+
+    /// Receive a request and respond to it
+    ///
+    /// Blocks until a command frame was received.
+    /// Returns the command served or [None] if the frame wasn't addressed to the emulator
+    /// or no response was sent (see [Fault::NoResponse]).
+    pub fn serve(&mut self) -> Result<Option<CommandCode>, Error<U::Error>> {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let request =
+            Frame::decode_command_with(&mut self.uart, &mut info_buf, self.options)?.frame;
+        let Cid2::Command(command) = request.cid2 else {
+            return Err(Error::UnsupportedControlIdentifier);
+        };
+        if request.cid1 != self.cid1 || !self.is_addressed(request.adr) {
+            debug!("Ignoring {command:?} for {:#04X}", request.adr);
+            return Ok(None);
+        }
+
+        let mut payload = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let result = match Request::from_frame(&request) {
+            Ok(parsed) => self.answer(parsed, &mut payload),
+            Err(e) => {
+                warn!("Invalid {command:?} request: {e:?}");
+                Err(ResponseCode::CommandFormatErr)
+            }
+        };
+        let (mut code, mut len) = match result {
+            Ok(len) => (ResponseCode::Normal, len),
+            Err(code) => (code, 0),
+        };
+
+        self.responses = self.responses.wrapping_add(1);
+        let fault = match self.fault {
+            Some((fault, every)) if self.responses.is_multiple_of(every) => Some(fault),
+            _ => None,
+        };
+        match fault {
+            Some(Fault::NoResponse) => {
+                debug!("Injecting fault: not responding to {command:?}");
+                return Ok(None);
+            }
+            Some(Fault::Response(fault_code)) => {
+                debug!("Injecting fault: responding {fault_code:?} to {command:?}");
+                (code, len) = (fault_code, 0);
+            }
+            _ => {}
+        }
+
+        let response = Frame::new_with_cid1(
+            self.version,
+            request.adr,
+            self.cid1,
+            code.into(),
+            &payload[..len],
+        );
+        if fault == Some(Fault::BadChecksum) {
+            debug!("Injecting fault: bad checksum in response to {command:?}");
+            // The last digit of `CHKSUM` precedes `EOI`
+            let target = 1 + 12 + len * 2 + 3;
+            response.encode(&mut Corrupt {
+                out: &mut self.uart,
+                pos: 0,
+                target,
+            })?;
+        } else {
+            response.encode(&mut self.uart)?;
+        }
+        self.uart.flush()?;
+        Ok(Some(command))
+    }
+
+    /// Whether frames to `adr` are answered
+    fn is_addressed(&self, adr: u8) -> bool {
+        adr == 0xFF || self.pack_index(adr).is_some()
+    }
+    /// Index of the pack at `address`
+    fn pack_index(&self, address: u8) -> Option<usize> {
+        let index = address.checked_sub(self.address)? as usize;
+        (index < self.packs.len()).then_some(index)
+    }
+    /// The pack at `address`, `0xFF` selects the first pack
+    fn pack(&self, address: u8) -> Result<&SimulatedPack, ResponseCode> {
+        let index = if address == 0xFF {
+            0
+        } else {
+            self.pack_index(address).ok_or(ResponseCode::AdrErr)?
+        };
+        self.packs.get(index).ok_or(ResponseCode::AdrErr)
+    }
+
+    /// Write the response payload to `request` into `buf`, returns its length
+    fn answer(&self, request: Request<'_>, buf: &mut [u8]) -> Result<usize, ResponseCode> {
+        let mut payload = Payload { buf, len: 0 };
+        match request {
+            Request::GetProtocolVersion => {}
+            Request::GetManufacturerInfo => {
+                payload.push(&padded::<10>(b"EMULATED"))?;
+                payload.push(&[1, 0])?;
+                payload.push(&padded::<20>(b"pylon-lfp-protocol"))?;
+            }
+            Request::GetSystemParameter => {
+                let pack = self.pack(0xFF)?;
+                payload.push(self.system_parameter(pack).as_bytes())?;
+            }
+            Request::GetAnalogValue { pack_address } => {
+                return self.analog_value(pack_address, payload.buf);
+            }
+            Request::GetAlarmInfo { pack_address } => {
+                let pack = self.pack(pack_address)?;
+                self.alarm_info(pack_address, pack, &mut payload)?;
+            }
+            Request::GetCharge { pack_address } => {
+                let pack = self.pack(pack_address)?;
+                let cells = pack.config.cells as f32;
+                let capacity = pack.config.capacity;
+                payload.push(&[pack_address])?;
+                payload.push(&self.total_voltage(3.55 * cells).to_be_bytes())?;
+                payload.push(&self.total_voltage(2.9 * cells).to_be_bytes())?;
+                payload.push(&self.current(0.5 * capacity).to_be_bytes())?;
+                payload.push(&self.current(-capacity).to_be_bytes())?;
+                // Charge and discharge enabled
+                payload.push(&[0b1100_0000])?;
+            }
+            Request::GetSerialNumber { pack_address } => {
+                self.pack(pack_address)?;
+                payload.push(&[pack_address])?;
+                let mut serial = *b"EMU0000000000000";
+                serial[13..].copy_from_slice(&digits(pack_address));
+                payload.push(&serial)?;
+            }
+            Request::Other {
+                command: CommandCode::GetQuantityOfPack,
+                ..
+            } => payload.push(&[self.packs.len() as u8])?,
+            Request::Other {
+                command: CommandCode::GetFirmwareInfo,
+                info,
+            } => {
+                let pack_address = info.first().copied().unwrap_or(0xFF);
+                self.pack(pack_address)?;
+                payload.push(&[pack_address, 1, 0, 1, 0, 0])?;
+            }
+            // Settings and controls are acknowledged without effect
+            Request::Other { command, .. } => debug!("Acknowledging {command:?}"),
+        }
+        Ok(payload.len)
+    }
+    /// "_get analog value_" payload of the pack at `pack_address` or all packs for `0xFF`
+    fn analog_value(&self, pack_address: u8, buf: &mut [u8]) -> Result<usize, ResponseCode> {
+        let packs = if pack_address == 0xFF {
+            &self.packs[..]
+        } else {
+            let index = self.pack_index(pack_address).ok_or(ResponseCode::AdrErr)?;
+            &self.packs[index..=index]
+        };
+        let mut builder = AnalogValueBuilder::new(buf, ChangeFlags::new(false, false))
+            .map_err(|_| ResponseCode::CommunicationErr)?;
+        for pack in packs {
+            let mut cells = [Volt::new(0); MAX_CELLS];
+            for (raw, voltage) in cells.iter_mut().zip(pack.cell_voltages()) {
+                *raw = Volt::new(unsigned(voltage, self.scaling.cell_voltage));
+            }
+            let mut temperatures = [Temperature::new(0); MAX_TEMPERATURES];
+            for (raw, temperature) in temperatures.iter_mut().zip(pack.temperatures()) {
+                *raw = Temperature::new(unsigned(temperature, self.scaling.temperature));
+            }
+            let data: PackData<'_> = PackData::new(
+                &cells[..pack.config.cells as usize],
+                &temperatures[..pack.config.temperatures as usize],
+                Ampere::new(self.current(pack.current())),
+                Volt::new(self.total_voltage(pack.pack_voltage())),
+                AmpereHours::new(unsigned(pack.remaining(), self.scaling.amp_hours)),
+                AmpereHours::new(unsigned(pack.config.capacity, self.scaling.amp_hours)),
+                pack.cycles,
+            );
+            builder
+                .add_pack(&data)
+                .map_err(|_| ResponseCode::CommunicationErr)?;
+        }
+        Ok(builder.finish().len())
+    }
+    /// "_get alarm info_" payload of `pack`
+    fn alarm_info(
+        &self,
+        pack_address: u8,
+        pack: &SimulatedPack,
+        payload: &mut Payload<'_>,
+    ) -> Result<(), ResponseCode> {
+        let state = |value: f32, (lower, upper): (f32, f32)| {
+            if value < lower {
+                AlarmState::BELOW_LOWER_LIMIT
+            } else if value > upper {
+                AlarmState::ABOVE_UPPER_LIMIT
+            } else {
+                AlarmState::NORMAL
+            }
+        };
+        let cells = pack.config.cells as f32;
+        let capacity = pack.config.capacity;
+        payload.push(&[0, pack_address, pack.config.cells])?;
+        for voltage in pack.cell_voltages() {
+            payload.push(&[state(voltage, (2.7, 3.65)).get_raw()])?;
+        }
+        payload.push(&[pack.config.temperatures])?;
+        for temperature in pack.temperatures() {
+            payload.push(&[state(temperature, (253.15, 333.15)).get_raw()])?;
+        }
+        let current = pack.current();
+        payload.push(&[
+            state(current, (f32::MIN, 0.5 * capacity)).get_raw(),
+            state(pack.pack_voltage(), (2.7 * cells, 3.65 * cells)).get_raw(),
+            state(current, (-capacity, f32::MAX)).get_raw(),
+            // No user-defined status
+            0,
+        ])
+    }
+    /// System parameters of `pack`
+    fn system_parameter(&self, pack: &SimulatedPack) -> SystemParameter {
+        let cells = pack.config.cells as f32;
+        let capacity = pack.config.capacity;
+        let cell = |voltage: f32| Volt::new(unsigned(voltage, self.scaling.cell_voltage));
+        let total = |voltage: f32| Volt::new(self.total_voltage(voltage * cells));
+        let temperature =
+            |celsius: f32| Temperature::new(unsigned(celsius + 273.15, self.scaling.temperature));
+        SystemParameter {
+            unit_cell_voltage: cell(3.3),
+            unit_cell_low_voltage_threshold: cell(3.0),
+            unit_cell_under_voltage_threshold: cell(2.7),
+            charge_upper_limit_temp: temperature(45.),
+            charge_lower_limit_temp: temperature(0.),
+            charge_lower_limit_current: Ampere::new(self.current(0.5 * capacity)),
+            upper_limit_total_voltage: total(3.6),
+            lower_limit_total_voltage: total(2.9),
+            under_voltage_of_total_voltage: total(2.7),
+            discharge_upper_limit_temp: temperature(55.),
+            discharge_lower_limit_temp: temperature(-10.),
+            discharge_lower_limit_current: Ampere::new(self.current(-capacity)),
+        }
+    }
+    /// Raw total voltage
+    fn total_voltage(&self, voltage: f32) -> u16 {
+        unsigned(voltage, self.scaling.total_voltage)
+    }
+    /// Raw current
+    fn current(&self, current: f32) -> i16 {
+        let raw = current / number(self.scaling.current);
+        (if raw < 0. { raw - 0.5 } else { raw + 0.5 }) as i16
+    }
+}
+
+/// Raw unsigned value of `value` transmitted with exponent `exp`
+fn unsigned(value: f32, exp: i8) -> u16 {
+    (value / number(exp) + 0.5) as u16
+}
+
+/// Open-circuit voltage of a cell at `soc`
+fn open_circuit_voltage(soc: f32) -> f32 {
+    let upper = OCV
+        .iter()
+        .position(|(s, _)| *s >= soc)
+        .unwrap_or(OCV.len() - 1);
+    if upper == 0 {
+        return OCV[0].1;
+    }
+    let (s0, v0) = OCV[upper - 1];
+    let (s1, v1) = OCV[upper];
+    v0 + (v1 - v0) * (soc - s0) / (s1 - s0)
+}
+
+/// `text` padded with spaces to `N` bytes
+fn padded<const N: usize>(text: &[u8]) -> [u8; N] {
+    let mut padded = [b' '; N];
+    let len = text.len().min(N);
+    padded[..len].copy_from_slice(&text[..len]);
+    padded
+}
+
+/// Three decimal ASCII digits of `value`
+fn digits(value: u8) -> [u8; 3] {
+    [
+        b'0' + value / 100,
+        b'0' + value / 10 % 10,
+        b'0' + value % 10,
+    ]
+}
+
+/// Response payload written to a buffer
+struct Payload<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+impl Payload<'_> {
+    fn push(&mut self, bytes: &[u8]) -> Result<(), ResponseCode> {
+        self.buf
+            .get_mut(self.len..self.len + bytes.len())
+            .ok_or(ResponseCode::CommunicationErr)?
+            .copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+}
+
+/// Deterministic pseudo-random numbers (xorshift)
+struct Random(u32);
+impl Random {
+    fn new(seed: u32) -> Self {
+        Random(seed.wrapping_mul(0x9E37_79B9) | 1)
+    }
+    /// Next number from `-1.0` to `1.0`
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 23) as f32 - 1.
+    }
+}
+
+/// Writer replacing the byte at `target` with another hex digit
+struct Corrupt<'w, W> {
+    out: &'w mut W,
+    /// Number of bytes written
+    pos: usize,
+    target: usize,
+}
+impl<W: Write> embedded_io::ErrorType for Corrupt<'_, W> {
+    type Error = W::Error;
+}
+impl<W: Write> Write for Corrupt<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let written = if (self.pos..self.pos + buf.len()).contains(&self.target) {
+            if self.pos == self.target {
+                let digit = if buf[0] == b'0' { b'1' } else { b'0' };
+                self.out.write(&[digit])?
+            } else {
+                self.out.write(&buf[..self.target - self.pos])?
+            }
+        } else {
+            self.out.write(buf)?
+        };
+        self.pos += written;
+        Ok(written)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{Emulator, Fault, PackConfig, SimulatedPack};
+    use crate::{
+        CommandCode, Error, Frame, MAX_UNENCODED_PAYLOAD_LEN, ResponseCode,
+        commands::{AlarmInfo, AnalogValueResponse, Request},
+        dialect::Dialect,
+        pace::{Pace, PaceAnalogValue},
+        testing::EncodedFrame,
+    };
+
+    /// Transport reading requests from a slice and recording responses
+    struct Loopback {
+        requests: &'static [u8],
+        responses: Vec<u8>,
+    }
+    impl embedded_io::ErrorType for Loopback {
+        type Error = core::convert::Infallible;
+    }
+    impl embedded_io::Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            embedded_io::Read::read(&mut self.requests, buf)
+        }
+    }
+    impl embedded_io::Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.responses.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    fn stack() -> [SimulatedPack; 2] {
+        [
+            SimulatedPack::new(PackConfig::default(), 0),
+            SimulatedPack::new(
+                PackConfig {
+                    cells: 16,
+                    ..PackConfig::default()
+                },
+                1,
+            ),
+        ]
+    }
+
+    #[test]
+    fn serve_analog_value() {
+        let mut packs = stack();
+        let uart = Loopback {
+            // Analog values of all packs and alarm info of pack 2
+            requests: b"~28FF4642E002FFFCD7\r~28024644E00202FD29\r",
+            responses: Vec::new(),
+        };
+        let mut emulator = Emulator::new(uart, &mut packs);
+        assert_eq!(emulator.serve().unwrap(), Some(CommandCode::GetAnalogValue));
+        assert_eq!(emulator.serve().unwrap(), Some(CommandCode::GetAlarmInfo));
+        let uart = emulator.into_inner();
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut responses = uart.responses.as_slice();
+        let frame = Frame::decode(&mut responses, &mut info_buf).expect("Error decoding response");
+        assert_eq!(frame.adr, 0xFF);
+        let response = AnalogValueResponse::from_bytes(frame.info).expect("Failed to parse");
+        assert_eq!(response.get_pack_count(), 2);
+        for (i, pack) in packs.iter().enumerate() {
+            let scaled = response
+                .get_pack_scaled(i as u8, Default::default())
+                .expect("Failed to parse PackData");
+            assert_eq!(scaled.cell_voltages().len(), pack.config().cells as usize);
+            assert!((scaled.pack_voltage() - pack.pack_voltage()).abs() < 0.01);
+            assert!((scaled.pack_remaining() - pack.remaining()).abs() < 0.01);
+            assert!((scaled.pack_current() - pack.current()).abs() < 0.01);
+        }
+
+        let frame = Frame::decode(&mut responses, &mut info_buf).expect("Error decoding response");
+        let alarms = AlarmInfo::from_bytes(frame.info).expect("Failed to parse");
+        assert_eq!(alarms.pack_address, 2);
+        assert_eq!(alarms.cell_voltage_alarms.len(), 16);
+        assert!(alarms.cell_voltage_alarms.iter().all(|a| a.is_normal()));
+    }
+    #[test]
+    fn serve_large_stack() {
+        let mut packs: Vec<_> = (0..16)
+            .map(|i| SimulatedPack::new(PackConfig::default(), i))
+            .collect();
+        let uart = Loopback {
+            requests: b"~28FF4642E002FFFCD7\r",
+            responses: Vec::new(),
+        };
+        let mut emulator = Emulator::new(uart, &mut packs);
+        assert_eq!(emulator.serve().unwrap(), Some(CommandCode::GetAnalogValue));
+        let uart = emulator.into_inner();
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let frame = Frame::decode(&mut uart.responses.as_slice(), &mut info_buf)
+            .expect("Error decoding response");
+        let response = AnalogValueResponse::from_bytes(frame.info).expect("Failed to parse");
+        assert_eq!(response.get_pack_count(), 16);
+    }
+    #[test]
+    fn serve_dialect() {
+        let request = EncodedFrame::request::<Pace>(&Request::GetAnalogValue { pack_address: 1 });
+        let mut packs = stack();
+        let uart = Loopback {
+            requests: Vec::leak(request.to_vec()),
+            responses: Vec::new(),
+        };
+        let mut emulator = Emulator::new(uart, &mut packs).dialect::<Pace>();
+        assert_eq!(emulator.serve().unwrap(), Some(CommandCode::GetAnalogValue));
+        let uart = emulator.into_inner();
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut responses = uart.responses.as_slice();
+        let frame = Frame::decode(&mut responses, &mut info_buf).expect("Error decoding response");
+        assert_eq!(frame.ver, Pace::VERSION);
+        // Currents are transmitted in 10 mA
+        let response = PaceAnalogValue::from_bytes(frame.info).expect("Failed to parse");
+        let pack = response.get_pack(0).expect("Failed to parse PackData");
+        assert!((pack.pack.pack_current.get_ampere() - packs[0].current()).abs() < 0.01);
+    }
+    #[test]
+    fn errors_and_faults() {
+        let mut packs = stack();
+        let uart = Loopback {
+            // Analog value of the missing pack 3, to another address, then twice of pack 1
+            requests: b"~28FF4642E00203FD00\r~28054642E00201FD29\r~28014642E00201FD2D\r~28014642E00201FD2D\r",
+            responses: Vec::new(),
+        };
+        let mut emulator = Emulator::new(uart, &mut packs);
+        emulator.inject_fault(Some((Fault::BadChecksum, 3)));
+        assert_eq!(emulator.serve().unwrap(), Some(CommandCode::GetAnalogValue));
+        assert_eq!(emulator.serve().unwrap(), None);
+        assert_eq!(emulator.serve().unwrap(), Some(CommandCode::GetAnalogValue));
+        assert_eq!(emulator.serve().unwrap(), Some(CommandCode::GetAnalogValue));
+        let uart = emulator.into_inner();
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut responses = uart.responses.as_slice();
+        let frame = Frame::decode(&mut responses, &mut info_buf);
+        assert!(matches!(frame, Err(Error::Response(ResponseCode::AdrErr))));
+        assert!(Frame::decode(&mut responses, &mut info_buf).is_ok());
+        let frame = Frame::decode(&mut responses, &mut info_buf);
+        assert!(matches!(frame, Err(Error::Cecksum)));
+        assert!(responses.is_empty());
+    }
+    #[test]
+    fn simulate_packs() {
+        let mut pack = SimulatedPack::new(
+            PackConfig {
+                current: -50.,
+                initial_soc: 0.5,
+                ..PackConfig::default()
+            },
+            0,
+        );
+        let voltages: Vec<f32> = pack.cell_voltages().collect();
+        let spread = voltages.iter().cloned().fold(f32::MIN, f32::max)
+            - voltages.iter().cloned().fold(f32::MAX, f32::min);
+        assert!(spread > 0. && spread <= 0.01);
+
+        // Discharge until the current reverses
+        let soc = pack.soc();
+        pack.advance(Duration::from_secs(360));
+        assert!(pack.soc() < soc);
+        for _ in 0..10 {
+            pack.advance(Duration::from_secs(360));
+        }
+        assert!(pack.current() > 0.);
+        assert!(pack.temperatures().all(|t| t > 298.));
+    }
+}
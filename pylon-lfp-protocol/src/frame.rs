@@ -5,6 +5,9 @@ use core::fmt::Display;
 use log::{debug, warn};
 use util::*;
 
+mod decoder;
+pub use decoder::{FrameDecoder, FrameError};
+
 /// The maximum size of the ASCII encoded payload in bytes
 pub const MAX_ENCODED_PAYLOAD_LEN: usize = 4095;
 /// The maximum size of unencoded payload data in bytes that a message can contain
@@ -20,7 +23,7 @@ pub struct Frame<'a> {
     /// `CID1`
     ///
     /// Control identifier 1
-    cid1: Cid1,
+    pub cid1: Cid1,
     /// `CID2`
     ///
     /// Either a command or a response code.
@@ -45,11 +48,23 @@ impl<'a> Frame<'a> {
     ///
     /// `info` has to be the unencoded payload.
     pub fn new(ver: Version, adr: u8, cid2: Cid2, info: &'a [u8]) -> Frame<'a> {
+        Self::new_with_cid1(ver, adr, Cid1::BATTERY_DATA, cid2, info)
+    }
+    /// Construct a new frame with a vendor specific `CID1`
+    ///
+    /// `info` has to be the unencoded payload.
+    pub fn new_with_cid1(
+        ver: Version,
+        adr: u8,
+        cid1: Cid1,
+        cid2: Cid2,
+        info: &'a [u8],
+    ) -> Frame<'a> {
         let length = InfoLength::new(info.len() as u16 * 2);
         Self {
             ver,
             adr,
-            cid1: Cid1::BatteryData,
+            cid1,
             cid2,
             length,
             info,
@@ -64,167 +79,414 @@ impl<'a> Frame<'a> {
         reader: &mut R,
         info_buf: &'a mut [u8],
     ) -> Result<Frame<'a>, Error<R::Error>> {
-        let mut soi = [0; 1];
-        if reader.read(&mut soi)? != 1 {
-            return Err(Error::InvalidInput);
-        };
-        if soi[0] != Self::SOI {
-            return Err(Error::InvalidInput);
+        Self::decode_with(reader, info_buf, DecodeOptions::default()).map(|d| d.frame)
+    }
+    /// Decode a ASCII encoded packet with [DecodeOptions]
+    ///
+    /// See [Frame::decode].
+    pub fn decode_with<R: Read>(
+        reader: &mut R,
+        info_buf: &'a mut [u8],
+        options: DecodeOptions,
+    ) -> Result<Decoded<'a>, Error<R::Error>> {
+        Self::read_from(reader, FrameDecoder::with_options(info_buf, options))
+    }
+    /// Decode a ASCII encoded command frame (sent by a master, e.g. an inverter)
+    ///
+    /// `CID2` is decoded as [Cid2::Command], use [Request](crate::commands::Request)
+    /// to interpret the `INFO` field.
+    /// Returns [Error::UnsupportedControlIdentifier] for unknown command codes.
+    /// See [Frame::decode].
+    pub fn decode_command<R: Read>(
+        reader: &mut R,
+        info_buf: &'a mut [u8],
+    ) -> Result<Frame<'a>, Error<R::Error>> {
+        Self::decode_command_with(reader, info_buf, DecodeOptions::default()).map(|d| d.frame)
+    }
+    /// Decode a ASCII encoded command frame with [DecodeOptions]
+    ///
+    /// See [Frame::decode_command].
+    pub fn decode_command_with<R: Read>(
+        reader: &mut R,
+        info_buf: &'a mut [u8],
+        options: DecodeOptions,
+    ) -> Result<Decoded<'a>, Error<R::Error>> {
+        Self::read_from(reader, FrameDecoder::for_commands(info_buf, options))
+    }
+    /// Read a frame from `reader` into `decoder`
+    fn read_from<R: Read>(
+        reader: &mut R,
+        mut decoder: FrameDecoder<'a>,
+    ) -> Result<Decoded<'a>, Error<R::Error>> {
+        let mut chunk = [0u8; 32];
+        loop {
+            // Never read past the end of the frame
+            let len = decoder.needed().min(chunk.len());
+            let read = reader.read(&mut chunk[..len]);
+            // A frame only missing its `EOI` is complete at the end of input
+            if matches!(read, Ok(0) | Err(_))
+                && let Some(frame) = decoder.finish()
+            {
+                frame?;
+                break;
+            }
+            let read = read?;
+            if read == 0 {
+                return Err(Error::InvalidInput);
+            }
+            if let (_, Some(frame)) = decoder.push(&chunk[..read]) {
+                frame?;
+                break;
+            }
         }
+        Decoded::from_decoder(decoder)
+    }
+    /// Decode a ASCII encoded packet from an async reader
+    ///
+    /// Async counterpart to [Frame::decode].
+    #[cfg(feature = "async")]
+    pub async fn decode_async<R: embedded_io_async::Read>(
+        reader: &mut R,
+        info_buf: &'a mut [u8],
+    ) -> Result<Frame<'a>, Error<R::Error>> {
+        Self::decode_with_async(reader, info_buf, DecodeOptions::default())
+            .await
+            .map(|d| d.frame)
+    }
+    /// Decode a ASCII encoded packet with [DecodeOptions] from an async reader
+    ///
+    /// Async counterpart to [Frame::decode_with].
+    #[cfg(feature = "async")]
+    pub async fn decode_with_async<R: embedded_io_async::Read>(
+        reader: &mut R,
+        info_buf: &'a mut [u8],
+        options: DecodeOptions,
+    ) -> Result<Decoded<'a>, Error<R::Error>> {
+        let mut decoder = FrameDecoder::with_options(info_buf, options);
+        let mut chunk = [0u8; 32];
+        loop {
+            // Never read past the end of the frame
+            let len = decoder.needed().min(chunk.len());
+            let read = reader.read(&mut chunk[..len]).await;
+            // A frame only missing its `EOI` is complete at the end of input
+            if matches!(read, Ok(0) | Err(_))
+                && let Some(frame) = decoder.finish()
+            {
+                frame?;
+                break;
+            }
+            let read = read?;
+            if read == 0 {
+                return Err(Error::InvalidInput);
+            }
+            if let (_, Some(frame)) = decoder.push(&chunk[..read]) {
+                frame?;
+                break;
+            }
+        }
+        Decoded::from_decoder(decoder)
+    }
+    /// Construct a fully assembled ASCII/HEX encoded packet of data
+    ///
+    /// Encodes command frames as well as response frames.
+    /// According to the specification responses signaling an error ([ResponseCode] other than
+    /// [ResponseCode::Normal]) carry an empty `INFO` field, `info` is encoded as given though.
+    ///
+    /// Returns [Error::InvalidInput] when the payload is to large,
+    /// (larger than [MAX_UNENCODED_PAYLOAD_LEN]).
+    pub fn encode<W: Write>(&self, out: &mut W) -> Result<(), Error<W::Error>> {
+        let (header, mut chksum) = self.encode_header()?;
+        out.write_all(&header)?;
 
-        let mut checksum = Checksum::new();
-
-        let mut u8_buf = [0u8; 2];
-        let mut u16_buf = [0u8; 4];
-
-        // Decode version
-        reader.read_exact(&mut u8_buf)?;
-        checksum.update(&u8_buf);
-        let ver = Version::decode_hex(&u8_buf)?;
-        debug!("Decoded ver {ver}");
-
-        // Decode address
-        reader.read_exact(&mut u8_buf)?;
-        checksum.update(&u8_buf);
-        let adr = u8_from_hex(&u8_buf)?;
-        debug!("Decoded adr {adr:#04X}");
+        // write data
+        for byte in self.info {
+            let encoded = u8_encode_hex(*byte);
+            chksum.update(&encoded);
+            out.write_all(&encoded)?;
+        }
 
-        // Decode CID1
-        reader.read_exact(&mut u8_buf)?;
-        checksum.update(&u8_buf);
-        Cid1::decode_hex(&u8_buf)?;
-        debug!("CID1 ok");
-
-        // Decode CID2
-        reader.read_exact(&mut u8_buf)?;
-        checksum.update(&u8_buf);
-        let cid2 = ResponseCode::decode_hex(&u8_buf)?;
-        debug!("Decoded response code: {cid2:?}");
-
-        // Decode LENGTH
-        reader.read_exact(&mut u16_buf)?;
-        checksum.update(&u16_buf);
-        let length = InfoLength::decode_hex(&u16_buf)?;
-        length.validate().map_err(|_| Error::Cecksum)?;
-        debug!("Decoded valid payload length: {}", length.length());
+        out.write_all(&encode_trailer(chksum))?;
 
-        // Return if we can't read the full frame
-        if info_buf.len() < length.length() as usize / 2 {
-            warn!(
-                "Buffer for payload to small ({} < {} ({} hex values))",
-                info_buf.len(),
-                length.length() / 2,
-                length.length()
-            );
-            return Err(Error::Internal);
-        }
+        Ok(())
+    }
+    /// Construct a fully assembled ASCII/HEX encoded packet of data to an async writer
+    ///
+    /// Async counterpart to [Frame::encode].
+    #[cfg(feature = "async")]
+    pub async fn encode_async<W: embedded_io_async::Write>(
+        &self,
+        out: &mut W,
+    ) -> Result<(), Error<W::Error>> {
+        let (header, mut chksum) = self.encode_header()?;
+        out.write_all(&header).await?;
 
-        for byte in &mut info_buf[..length.length() as usize / 2] {
-            reader.read_exact(&mut u8_buf)?;
-            checksum.update(&u8_buf);
-            *byte = u8_from_hex(&u8_buf)?;
+        // write data
+        for byte in self.info {
+            let encoded = u8_encode_hex(*byte);
+            chksum.update(&encoded);
+            out.write_all(&encoded).await?;
         }
 
-        // Read CHKSUM
-        reader.read_exact(&mut u16_buf)?;
-        let chksum = u16_from_hex(&u16_buf)?;
-        let calculated_checksum = checksum.finalize();
-        debug!("Decoded checksum {chksum}, calculated checksum {calculated_checksum}");
-        if chksum != calculated_checksum {
-            return Err(Error::Cecksum);
-        }
+        out.write_all(&encode_trailer(chksum)).await?;
 
-        // Read EOI
-        let mut eoi = [0; 1];
-        if reader.read(&mut eoi)? != 1 {
-            return Err(Error::InvalidInput);
-        };
-        if eoi[0] != Self::EOI {
-            return Err(Error::InvalidInput);
-        }
+        Ok(())
+    }
 
-        if cid2.is_err() {
-            return Err(Error::Response(cid2));
-        }
-        Ok(Frame::new(
-            ver,
-            adr,
-            cid2.into(),
-            &info_buf[..length.length() as usize],
-        ))
+    /// `CHKSUM` over the ASCII characters between `SOI` and `CHKSUM`
+    pub fn checksum(ascii: &[u8]) -> u16 {
+        let mut checksum = Checksum::new();
+        checksum.update(ascii);
+        checksum.finalize()
     }
-    /// Construct a fully assembled ASCII/HEX encoded packet of data
+
+    /// Encode `SOI` and header (`VER` to `LENGTH`)
     ///
-    /// Returns [Error::InvalidInput] when the payload is to large,
-    /// (larger than [MAX_UNENCODED_PAYLOAD_LEN]).
-    pub fn encode<W: Write>(&self, out: &mut W) -> Result<(), Error<W::Error>> {
+    /// Returns the checksum over the header to be updated with the `INFO` field.
+    fn encode_header<T: embedded_io::Error>(
+        &self,
+    ) -> Result<([u8; 1 + HEADER_LEN], Checksum), Error<T>> {
         if self.info.len() > MAX_UNENCODED_PAYLOAD_LEN {
             return Err(Error::InvalidInput);
         }
-        let Cid2::Command(cmd) = self.cid2 else {
-            return Err(Error::Internal);
-        };
+        let mut header = [0u8; 1 + HEADER_LEN];
+        header[0] = Self::SOI;
+        header[1..3].copy_from_slice(&self.ver.encode_hex());
+        header[3..5].copy_from_slice(&self.encode_adr());
+        header[5..7].copy_from_slice(&self.cid1.encode_hex());
+        header[7..9].copy_from_slice(&self.cid2.encode_hex());
+        header[9..].copy_from_slice(&self.length.encode_hex());
+
         let mut chksum = Checksum::new();
+        chksum.update(&header[1..]);
+        Ok((header, chksum))
+    }
+
+    fn encode_adr(&self) -> [u8; 2] {
+        u8_encode_hex(self.adr)
+    }
+}
+
+/// Options for decoding frames
+///
+/// By default frames have to follow the specification.
+/// Tolerances for common deviations of BMS firmware can be enabled individually,
+/// every tolerated deviation is reported in the [DecodeWarnings] of the frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeOptions {
+    /// Hunt for `SOI` and discard bytes until a valid frame is found
+    ///
+    /// Line noise, echoes of RS485 adapters or leftovers of earlier responses
+    /// are skipped instead of failing the decoding.
+    pub resync: bool,
+    /// Accept lowercase hex digits
+    pub lowercase_hex: bool,
+    /// Accept a `CHKSUM` off by one from the calculated checksum
+    pub checksum_off_by_one: bool,
+    /// Accept frames without `EOI`
+    ///
+    /// The frame is complete after `CHKSUM` if the next byte is a `SOI`,
+    /// any other character or the end of input.
+    /// When reading from a blocking transport this only completes after a read timeout.
+    pub missing_eoi: bool,
+    /// Accept a `LENGTH` with a wrong `LCHKSUM`
+    pub bad_length_checksum: bool,
+}
+impl DecodeOptions {
+    /// Decode frames following the specification only
+    pub const STRICT: DecodeOptions = DecodeOptions {
+        resync: false,
+        lowercase_hex: false,
+        checksum_off_by_one: false,
+        missing_eoi: false,
+        bad_length_checksum: false,
+    };
+    /// Enable all tolerances (without [DecodeOptions::resync])
+    pub const LENIENT: DecodeOptions = DecodeOptions {
+        resync: false,
+        lowercase_hex: true,
+        checksum_off_by_one: true,
+        missing_eoi: true,
+        bad_length_checksum: true,
+    };
+}
+
+/// Deviations from the specification tolerated while decoding a frame
+///
+/// See [DecodeOptions].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeWarnings {
+    /// Lowercase hex digits were encountered
+    pub lowercase_hex: bool,
+    /// `CHKSUM` was off by one
+    pub checksum_off_by_one: bool,
+    /// `EOI` was missing
+    pub missing_eoi: bool,
+    /// `LCHKSUM` of `LENGTH` was wrong
+    pub bad_length_checksum: bool,
+}
+impl DecodeWarnings {
+    /// Whether the frame followed the specification
+    pub fn is_empty(&self) -> bool {
+        *self == DecodeWarnings::default()
+    }
+}
 
-        // write SOI
-        out.write_all(&[Self::SOI])?;
+/// A frame decoded by [Frame::decode_with]
+#[derive(Debug)]
+pub struct Decoded<'a> {
+    pub frame: Frame<'a>,
+    /// Number of bytes discarded before the frame (see [DecodeOptions::resync])
+    pub skipped: usize,
+    /// Tolerated deviations from the specification
+    pub warnings: DecodeWarnings,
+}
+impl<'a> Decoded<'a> {
+    /// Take the completed frame out of a decoder
+    fn from_decoder<T: embedded_io::Error>(decoder: FrameDecoder<'a>) -> Result<Self, Error<T>> {
+        let skipped = decoder.skipped();
+        let warnings = decoder.warnings();
+        let frame = decoder.into_frame().ok_or(Error::Internal)??;
+        Ok(Decoded {
+            frame,
+            skipped,
+            warnings,
+        })
+    }
+}
 
-        // encode version
-        let ver = self.ver.encode_hex();
-        chksum.update(&ver);
-        out.write(&ver)?;
+/// Length of the ASCII encoded header (`VER` to `LENGTH`) following the `SOI`
+const HEADER_LEN: usize = 12;
+/// Length of the ASCII encoded `CHKSUM`
+const CHKSUM_LEN: usize = 4;
+/// Length of the ASCII encoded trailer (`CHKSUM` and `EOI`)
+const TRAILER_LEN: usize = CHKSUM_LEN + 1;
 
-        // encode address
-        let adr = self.encode_adr();
-        chksum.update(&adr);
-        out.write(&adr)?;
+/// Decoded header of a response frame
+#[derive(Debug)]
+struct Header {
+    ver: Version,
+    adr: u8,
+    cid1: Cid1,
+    cid2: Cid2,
+    length: InfoLength,
+}
+impl Header {
+    /// Decode the header and validate the `LENGTH` against the `info_capacity`
+    ///
+    /// `CID2` is decoded according to `kind`.
+    fn decode(
+        ascii: &[u8; HEADER_LEN],
+        kind: Cid2Kind,
+        info_capacity: usize,
+        options: &DecodeOptions,
+        warnings: &mut DecodeWarnings,
+    ) -> Result<Header, FrameError> {
+        let ver = Version::decode_hex(&[ascii[0], ascii[1]])?;
+        debug!("Decoded ver {ver}");
 
-        // encode CID1
-        let cid1 = self.cid1.encode_hex();
-        chksum.update(&cid1);
-        out.write(&cid1)?;
+        let adr = u8_from_hex(&[ascii[2], ascii[3]])?;
+        debug!("Decoded adr {adr:#04X}");
 
-        // encode CID2
-        let cid2 = cmd.encode_hex();
-        chksum.update(&cid2);
-        out.write(&cid2)?;
+        let cid1 = Cid1::decode_hex(&[ascii[4], ascii[5]])?;
+        debug!("Decoded CID1: {:#04X}", cid1.0);
 
-        // encode LENGTH
-        let len = self.length.encode_hex();
-        chksum.update(&len);
-        out.write(&len)?;
+        let cid2 = Cid2::decode_hex(&[ascii[6], ascii[7]], kind)?;
+        debug!("Decoded CID2: {cid2:?}");
 
-        // write data
-        for byte in self.info {
-            let encoded = u8_encode_hex(*byte);
-            chksum.update(&encoded);
-            out.write_all(&encoded)?;
+        let length = InfoLength::decode_hex(&[ascii[8], ascii[9], ascii[10], ascii[11]])?;
+        if !length.is_valid() {
+            if !options.bad_length_checksum {
+                return Err(FrameError::Checksum);
+            }
+            warn!("Tolerating bad LCHKSUM of LENGTH {:#06X}", length.0);
+            warnings.bad_length_checksum = true;
         }
+        debug!("Decoded payload length: {}", length.length());
 
-        // write checksum
-        let chksum = chksum.finalize();
-        out.write_all(u16_encode_hex(chksum).as_slice())?;
-
-        // write EOI
-        out.write_all(&[Self::EOI])?;
+        // Return if we can't read the full frame
+        if info_capacity < length.length() as usize / 2 {
+            warn!(
+                "Buffer for payload to small ({} < {} ({} hex values))",
+                info_capacity,
+                length.length() / 2,
+                length.length()
+            );
+            return Err(FrameError::BufferTooSmall);
+        }
 
-        Ok(())
+        Ok(Header {
+            ver,
+            adr,
+            cid1,
+            cid2,
+            length,
+        })
+    }
+    /// Length of the unencoded `INFO` field
+    fn info_len(&self) -> usize {
+        self.length.length() as usize / 2
+    }
+    /// Assemble the frame after the `INFO` field was decoded into `info_buf`
+    ///
+    /// Returns [FrameError::Response] if the BMS signaled an error.
+    fn frame<'f>(&self, info_buf: &'f [u8]) -> Result<Frame<'f>, FrameError> {
+        if let Cid2::Response(code) = self.cid2
+            && code.is_err()
+        {
+            return Err(FrameError::Response(code));
+        }
+        Ok(self.frame_unchecked(info_buf))
+    }
+    /// Assemble the frame, including responses signaling an error
+    fn frame_unchecked<'f>(&self, info_buf: &'f [u8]) -> Frame<'f> {
+        Frame::new_with_cid1(
+            self.ver,
+            self.adr,
+            self.cid1,
+            self.cid2,
+            &info_buf[..self.info_len()],
+        )
     }
+}
 
-    fn encode_adr(&self) -> [u8; 2] {
-        u8_encode_hex(self.adr)
+/// Check `CHKSUM` of a frame against the `checksum` over the frame
+fn check_checksum(
+    ascii: &[u8; CHKSUM_LEN],
+    checksum: &mut Checksum,
+    options: &DecodeOptions,
+    warnings: &mut DecodeWarnings,
+) -> Result<(), FrameError> {
+    let chksum = u16_from_hex(ascii)?;
+    let calculated_checksum = checksum.finalize();
+    debug!("Decoded checksum {chksum}, calculated checksum {calculated_checksum}");
+    if chksum != calculated_checksum {
+        let off_by_one = chksum == calculated_checksum.wrapping_add(1)
+            || chksum == calculated_checksum.wrapping_sub(1);
+        if !(off_by_one && options.checksum_off_by_one) {
+            return Err(FrameError::Checksum);
+        }
+        warn!("Tolerating checksum {chksum:#06X} off by one from {calculated_checksum:#06X}");
+        warnings.checksum_off_by_one = true;
     }
+    Ok(())
+}
+
+/// Encode `CHKSUM` and `EOI`
+fn encode_trailer(mut checksum: Checksum) -> [u8; TRAILER_LEN] {
+    let mut trailer = [Frame::EOI; TRAILER_LEN];
+    trailer[..4].copy_from_slice(&u16_encode_hex(checksum.finalize()));
+    trailer
 }
 
 /// Encoded protocol version
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Version(u8);
 impl Version {
     /// Create a new [Version] from `major` and `minor`
     ///
     /// _Note:_ `major` and `minor` are only stored in 4bit.
     /// Values greater than `15` will be truncated.
-    pub fn new(major: u8, minor: u8) -> Self {
+    pub const fn new(major: u8, minor: u8) -> Self {
         Self((major << 4) ^ (minor & 0b1111))
     }
     pub fn major(&self) -> u8 {
@@ -259,34 +521,67 @@ impl Display for Version {
 /// `CID1` control identifier
 ///
 /// RS232 (ver. 2.8) and RS485 (ver. 3.3) protocols
-/// only specify one `CID1` which is `BatteryData`.
-#[non_exhaustive]
-#[derive(Debug, Clone, Copy)]
-enum Cid1 {
-    BatteryData = 0x46,
-}
+/// only specify one `CID1` which is [Cid1::BATTERY_DATA].
+/// Vendor dialects reusing the frame format may use a different one,
+/// so any value is accepted when decoding a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cid1(pub u8);
 impl Cid1 {
+    /// Battery data (Pylontech)
+    pub const BATTERY_DATA: Cid1 = Cid1(0x46);
+    /// Battery data (Seplos BMS v2)
+    pub const SEPLOS_BATTERY_DATA: Cid1 = Cid1(0x4A);
+
     fn encode_hex(&self) -> [u8; 2] {
-        u8_encode_hex(*self as u8)
+        u8_encode_hex(self.0)
     }
     pub fn decode_hex(ascii: &[u8; 2]) -> Result<Cid1, DecodeError> {
-        let value = u8_from_hex(ascii)?;
-        if value == Self::BatteryData as u8 {
-            Ok(Self::BatteryData)
-        } else {
-            Err(DecodeError::UnknownVariant)
-        }
+        Ok(Self(u8_from_hex(ascii)?))
     }
 }
 
 /// `CID2` control identifier
 ///
 /// Eiter a command code or a response code.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Cid2 {
     Command(CommandCode),
     Response(ResponseCode),
 }
+impl Cid2 {
+    fn encode_hex(&self) -> [u8; 2] {
+        match self {
+            Cid2::Command(command) => command.encode_hex(),
+            Cid2::Response(code) => code.encode_hex(),
+        }
+    }
+    fn decode_hex(ascii: &[u8; 2], kind: Cid2Kind) -> Result<Cid2, DecodeError> {
+        match kind {
+            Cid2Kind::Command => Ok(Cid2::Command(CommandCode::decode_hex(ascii)?)),
+            Cid2Kind::Response => Ok(Cid2::Response(ResponseCode::decode_hex(ascii)?)),
+            Cid2Kind::Any { prefer_command } => {
+                let command = CommandCode::decode_hex(ascii).map(Cid2::Command);
+                let response = ResponseCode::decode_hex(ascii).map(Cid2::Response);
+                if prefer_command {
+                    command.or(response)
+                } else {
+                    response.or(command)
+                }
+            }
+        }
+    }
+}
+
+/// Interpretation of `CID2` when decoding a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cid2Kind {
+    Response,
+    Command,
+    /// Either, codes valid as both (e.g. `0x90`) are decoded as command if `prefer_command` is set
+    Any {
+        prefer_command: bool,
+    },
+}
 impl From<CommandCode> for Cid2 {
     fn from(value: CommandCode) -> Self {
         Self::Command(value)
@@ -301,7 +596,7 @@ impl From<ResponseCode> for Cid2 {
 /// `CID2` command codes (for both RS232 and RS485 protocol)
 ///
 /// Some of the command codes are only available in the RS232 protocol version.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum CommandCode {
     /// Get analog value, fixed point
@@ -383,6 +678,9 @@ pub enum ResponseCode {
     CommunicationErr = 0x91,
 }
 impl ResponseCode {
+    fn encode_hex(&self) -> [u8; 2] {
+        u8_encode_hex(*self as u8)
+    }
     fn is_err(&self) -> bool {
         !self.is_ok()
     }
@@ -433,19 +731,29 @@ impl InfoLength {
     fn encode_hex(&self) -> [u8; 4] {
         u16_encode_hex(self.0)
     }
-    fn decode_hex(ascii: &[u8; 4]) -> Result<Self, DecodeError> {
+    pub fn decode_hex(ascii: &[u8; 4]) -> Result<Self, DecodeError> {
         Ok(Self(u16_from_hex(ascii)?))
     }
-    fn validate(&self) -> Result<(), ()> {
-        let check = Self::new(self.length());
-        if self != &check { Err(()) } else { Ok(()) }
+    /// Whether the `LCHKSUM` matches the length
+    pub fn is_valid(&self) -> bool {
+        self.lchksum() == self.expected_lchksum()
     }
-    fn length(&self) -> u16 {
+    /// `LENID`, the number of ASCII characters of the `INFO` field
+    pub fn length(&self) -> u16 {
         self.0 & 0b1111_1111_1111
     }
+    /// `LCHKSUM` as received
+    pub fn lchksum(&self) -> u8 {
+        (self.0 >> 12) as u8
+    }
+    /// `LCHKSUM` matching the length
+    pub fn expected_lchksum(&self) -> u8 {
+        Self::new(self.length()).lchksum()
+    }
 }
 
 /// Checksum that can be updated multiple times before finalizing
+#[derive(Debug)]
 struct Checksum {
     acc: u32,
 }
@@ -484,6 +792,18 @@ mod tests {
         assert_eq!(length.0, EXPECTED);
     }
     #[test]
+    fn test_info_length_checksum() {
+        use super::InfoLength;
+        let length = InfoLength::new(18);
+        assert_eq!(length.length(), 18);
+        assert!(length.is_valid());
+
+        let length = InfoLength(0xC012);
+        assert!(!length.is_valid());
+        assert_eq!(length.lchksum(), 0xC);
+        assert_eq!(length.expected_lchksum(), 0xD);
+    }
+    #[test]
     fn test_version_encoding() {
         use super::Version;
 
@@ -574,6 +894,39 @@ mod tests {
             str::from_utf8(&buf)
         );
     }
+    #[test]
+    fn test_encode_response() {
+        use super::*;
+
+        /// Example response from specification
+        const EXPECTED: &[u8] = b"~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\r";
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut packet = EXPECTED;
+        let response = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+
+        let mut buf: Vec<u8> = Vec::new();
+        response.encode(&mut buf).expect("Error encoding frame");
+        assert_eq!(buf, EXPECTED);
+    }
+    #[test]
+    fn test_encode_error_response() {
+        use super::*;
+
+        const EXPECTED: &[u8] = b"~280246900000FDA1\r";
+        let packet = Frame::new(Version::default(), 2, ResponseCode::AdrErr.into(), &[]);
+
+        let mut buf: Vec<u8> = Vec::new();
+        packet.encode(&mut buf).expect("Error encoding frame");
+        assert_eq!(buf, EXPECTED);
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let decoded = Frame::decode(&mut buf.as_slice(), &mut info_buf);
+        assert!(matches!(
+            decoded,
+            Err(Error::Response(ResponseCode::AdrErr))
+        ));
+    }
 
     #[test]
     fn test_decode_frame1() {
@@ -634,4 +987,29 @@ mod tests {
 
         println!("{packet:#?}");
     }
+    #[test]
+    fn test_decode_lenient() {
+        use super::*;
+
+        /// Example response from specification in lowercase without `EOI`
+        const PACKET: &[u8] = b"~20014600c06e11010f0d450d440d450d440d450d440d3e0d450d4a0d4a0d4b0d4a0d4a0d4a0d4a050bc30bc30bc30bcd0bcd0000c725bf6802c3500002e013";
+
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut packet = PACKET;
+        let strict = Frame::decode(&mut packet, &mut info_buf);
+        assert!(matches!(strict, Err(Error::InvalidInput)));
+
+        let mut packet = PACKET;
+        let decoded = Frame::decode_with(&mut packet, &mut info_buf, DecodeOptions::LENIENT)
+            .expect("Error decoding packet");
+        assert_eq!(decoded.frame.info.len(), 55);
+        assert_eq!(
+            decoded.warnings,
+            DecodeWarnings {
+                lowercase_hex: true,
+                missing_eoi: true,
+                ..DecodeWarnings::default()
+            }
+        );
+    }
 }
@@ -0,0 +1,525 @@
+use super::{
+    CHKSUM_LEN, Checksum, Cid2Kind, DecodeOptions, DecodeWarnings, Frame, HEADER_LEN, Header,
+    check_checksum,
+};
+use log::{debug, warn};
+
+use crate::{ResponseCode, util::DecodeError, util::u8_from_hex};
+
+/// Errors encountered while decoding a frame with a [FrameDecoder]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// Invalid frame received
+    InvalidInput,
+    /// Bad checksum or `LENGTH` checksum
+    Checksum,
+    /// The payload doesn't fit the buffer of the decoder
+    BufferTooSmall,
+    /// Unknown control identifier received
+    UnsupportedControlIdentifier,
+    /// Error signaled by BMS
+    Response(ResponseCode),
+}
+impl<T: embedded_io::Error> From<FrameError> for crate::Error<T> {
+    fn from(value: FrameError) -> Self {
+        match value {
+            FrameError::InvalidInput => crate::Error::InvalidInput,
+            FrameError::Checksum => crate::Error::Cecksum,
+            FrameError::BufferTooSmall => crate::Error::Internal,
+            FrameError::UnsupportedControlIdentifier => crate::Error::UnsupportedControlIdentifier,
+            FrameError::Response(code) => crate::Error::Response(code),
+        }
+    }
+}
+impl From<DecodeError> for FrameError {
+    fn from(value: DecodeError) -> Self {
+        match value {
+            DecodeError::Hex => FrameError::InvalidInput,
+            DecodeError::UnknownVariant => FrameError::UnsupportedControlIdentifier,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for `SOI`
+    Soi,
+    /// Reading `VER` to `LENGTH`
+    Header,
+    /// Reading `INFO`
+    Info,
+    /// Reading `CHKSUM`
+    Checksum,
+    /// Waiting for `EOI`, `CHKSUM` is checked on completion
+    Eoi,
+    /// A frame was decoded
+    Complete,
+}
+
+/// Push-based frame decoder
+///
+/// Decodes frames from chunks of bytes as they arrive (e.g. from a UART interrupt),
+/// without blocking or allocating. The decoded `INFO` field is stored in a buffer
+/// provided on construction.
+///
+/// [Frame::decode] is built on top of this decoder.
+///
+/// With [DecodeOptions::resync] enabled the decoder doesn't report broken frames,
+/// it discards bytes until a valid frame is found (see [FrameDecoder::skipped]).
+/// Deviations tolerated by the other [DecodeOptions] are reported by [FrameDecoder::warnings].
+///
+/// ```rust
+/// use pylon_lfp_protocol::{FrameDecoder, MAX_UNENCODED_PAYLOAD_LEN};
+///
+/// let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+/// let mut decoder = FrameDecoder::new(&mut info_buf);
+///
+/// // Chunks as received by the UART
+/// let mut chunks: [&[u8]; 2] = [b"~2801460", b"0A006010203FC6E\r"];
+/// for chunk in &mut chunks {
+///     while !chunk.is_empty() {
+///         let (consumed, frame) = decoder.push(chunk);
+///         *chunk = &chunk[consumed..];
+///         match frame {
+///             Some(Ok(frame)) => assert_eq!(frame.info, &[1, 2, 3]),
+///             Some(Err(e)) => panic!("Failed to decode frame: {e:?}"),
+///             None => {}
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct FrameDecoder<'b> {
+    /// Buffer for the decoded `INFO` field
+    buf: &'b mut [u8],
+    state: State,
+    /// Number of ASCII characters read in the current state
+    pos: usize,
+    /// ASCII characters of the header, trailer or a pending `INFO` byte
+    ascii: [u8; HEADER_LEN],
+    checksum: Checksum,
+    header: Option<Header>,
+    options: DecodeOptions,
+    /// Number of bytes read since the `SOI` of the current frame
+    frame_len: usize,
+    /// Number of bytes discarded before the current frame
+    skipped: usize,
+    /// Deviations tolerated in the current frame
+    warnings: DecodeWarnings,
+    /// Interpretation of `CID2`
+    cid2: Cid2Kind,
+}
+
+impl<'b> FrameDecoder<'b> {
+    /// Create a new decoder storing the `INFO` of frames in `buf`
+    ///
+    /// Frames with a `INFO` longer than `buf` are rejected with [FrameError::BufferTooSmall].
+    pub fn new(buf: &'b mut [u8]) -> Self {
+        Self::with_options(buf, DecodeOptions::default())
+    }
+    /// Create a new decoder with [DecodeOptions]
+    pub fn with_options(buf: &'b mut [u8], options: DecodeOptions) -> Self {
+        FrameDecoder {
+            buf,
+            state: State::Soi,
+            pos: 0,
+            ascii: [0; HEADER_LEN],
+            checksum: Checksum::new(),
+            header: None,
+            options,
+            frame_len: 0,
+            skipped: 0,
+            warnings: DecodeWarnings::default(),
+            cid2: Cid2Kind::Response,
+        }
+    }
+    /// Create a new decoder for command frames (sent by a master, e.g. an inverter)
+    ///
+    /// `CID2` is decoded as [Cid2::Command](crate::Cid2::Command),
+    /// unknown command codes are rejected with [FrameError::UnsupportedControlIdentifier].
+    pub fn for_commands(buf: &'b mut [u8], options: DecodeOptions) -> Self {
+        FrameDecoder {
+            cid2: Cid2Kind::Command,
+            ..Self::with_options(buf, options)
+        }
+    }
+    /// Create a new decoder for command and response frames (e.g. listening on a bus)
+    ///
+    /// `CID2` is decoded as command or response code, whichever is valid.
+    /// Codes valid as both are resolved with [FrameDecoder::prefer_command].
+    pub fn for_bus(buf: &'b mut [u8], options: DecodeOptions) -> Self {
+        FrameDecoder {
+            cid2: Cid2Kind::Any {
+                prefer_command: true,
+            },
+            ..Self::with_options(buf, options)
+        }
+    }
+    /// Decode ambiguous `CID2` codes of the following frames as command if set
+    ///
+    /// Some codes are valid command and response codes (e.g. `0x90` is "_get quantity of pack_"
+    /// and [ResponseCode::AdrErr]). Only applies to decoders created with [FrameDecoder::for_bus].
+    pub fn prefer_command(&mut self, prefer_command: bool) {
+        if let Cid2Kind::Any { .. } = self.cid2 {
+            self.cid2 = Cid2Kind::Any { prefer_command };
+        }
+    }
+    /// Discard a partially decoded frame and wait for the next `SOI`
+    pub fn reset(&mut self) {
+        self.state = State::Soi;
+        self.pos = 0;
+        self.checksum = Checksum::new();
+        self.header = None;
+        self.frame_len = 0;
+        self.warnings = DecodeWarnings::default();
+    }
+    /// Number of bytes discarded before the current (or last completed) frame
+    ///
+    /// Only bytes discarded by [DecodeOptions::resync] are counted.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+    /// Deviations from the specification tolerated in the current (or last completed) frame
+    pub fn warnings(&self) -> DecodeWarnings {
+        self.warnings
+    }
+    /// Number of bytes needed to complete the current field
+    ///
+    /// Pushing at most this many bytes never reads past the end of a frame.
+    pub fn needed(&self) -> usize {
+        match self.state {
+            State::Soi | State::Eoi | State::Complete => 1,
+            State::Header => HEADER_LEN - self.pos,
+            State::Info => self.info_len() * 2 - self.pos,
+            State::Checksum => CHKSUM_LEN - self.pos,
+        }
+    }
+    /// Feed received bytes into the decoder
+    ///
+    /// Consumes bytes until a frame is complete or an error is encountered.
+    /// Returns the number of bytes consumed and the decoded frame or error, if any.
+    /// Remaining bytes have to be pushed again.
+    ///
+    /// After an error the decoder waits for the next `SOI`.
+    pub fn push(&mut self, data: &[u8]) -> (usize, Option<Result<Frame<'_>, FrameError>>) {
+        for (i, byte) in data.iter().enumerate() {
+            if self.state == State::Complete {
+                self.reset();
+                self.skipped = 0;
+            }
+            if self.state == State::Eoi && *byte == Frame::SOI && self.options.missing_eoi {
+                // The next frame started, leave its `SOI` for the next push
+                match self.complete(false) {
+                    Ok(()) => return (i, self.frame()),
+                    Err(e) if self.options.resync => self.discard(e),
+                    Err(e) => {
+                        self.reset();
+                        return (i, Some(Err(e)));
+                    }
+                }
+            }
+            if self.options.resync && *byte == Frame::SOI && self.state != State::Soi {
+                // `SOI` can't be part of a frame, a new frame started
+                debug!("Discarding {} bytes of incomplete frame", self.frame_len);
+                self.skipped += self.frame_len;
+                self.reset();
+            }
+            self.frame_len += 1;
+            match self.step(*byte) {
+                Ok(false) => {}
+                Ok(true) => return (i + 1, self.frame()),
+                Err(e) if self.options.resync => self.discard(e),
+                Err(e) => {
+                    self.reset();
+                    return (i + 1, Some(Err(e)));
+                }
+            }
+        }
+        (data.len(), None)
+    }
+    /// Signal the end of input
+    ///
+    /// Completes a frame only missing its `EOI` if [DecodeOptions::missing_eoi] is enabled,
+    /// returns [None] otherwise.
+    pub fn finish(&mut self) -> Option<Result<Frame<'_>, FrameError>> {
+        if self.state != State::Eoi || !self.options.missing_eoi {
+            return None;
+        }
+        match self.complete(false) {
+            Ok(()) => self.frame(),
+            Err(e) => {
+                self.reset();
+                Some(Err(e))
+            }
+        }
+    }
+    /// The decoded frame, if a frame is complete
+    ///
+    /// Returns [FrameError::Response] if the BMS signaled an error.
+    pub fn frame(&self) -> Option<Result<Frame<'_>, FrameError>> {
+        match (self.state, &self.header) {
+            (State::Complete, Some(header)) => Some(header.frame(self.buf)),
+            _ => None,
+        }
+    }
+    /// The decoded frame, including responses signaling an error
+    #[cfg(feature = "sniffer")]
+    pub(crate) fn frame_unchecked(&self) -> Option<Frame<'_>> {
+        match (self.state, &self.header) {
+            (State::Complete, Some(header)) => Some(header.frame_unchecked(self.buf)),
+            _ => None,
+        }
+    }
+    /// Consume the decoder, returning the decoded frame if a frame is complete
+    pub fn into_frame(self) -> Option<Result<Frame<'b>, FrameError>> {
+        let buf: &'b [u8] = self.buf;
+        match (self.state, self.header) {
+            (State::Complete, Some(header)) => Some(header.frame(buf)),
+            _ => None,
+        }
+    }
+
+    /// Process a single byte, returns `true` once a frame is complete
+    fn step(&mut self, byte: u8) -> Result<bool, FrameError> {
+        match self.state {
+            State::Soi => {
+                if byte != Frame::SOI {
+                    return Err(FrameError::InvalidInput);
+                }
+                self.state = State::Header;
+            }
+            State::Header => {
+                self.checksum.update(&[byte]);
+                self.ascii[self.pos] = self.hex_digit(byte)?;
+                self.pos += 1;
+                if self.pos == HEADER_LEN {
+                    let header = Header::decode(
+                        &self.ascii,
+                        self.cid2,
+                        self.buf.len(),
+                        &self.options,
+                        &mut self.warnings,
+                    )?;
+                    self.pos = 0;
+                    self.state = if header.info_len() == 0 {
+                        State::Checksum
+                    } else {
+                        State::Info
+                    };
+                    self.header = Some(header);
+                }
+            }
+            State::Info => {
+                self.checksum.update(&[byte]);
+                let byte = self.hex_digit(byte)?;
+                if self.pos.is_multiple_of(2) {
+                    self.ascii[0] = byte;
+                } else {
+                    self.buf[self.pos / 2] = u8_from_hex(&[self.ascii[0], byte])?;
+                }
+                self.pos += 1;
+                if self.pos == self.info_len() * 2 {
+                    self.pos = 0;
+                    self.state = State::Checksum;
+                }
+            }
+            State::Checksum => {
+                self.ascii[self.pos] = self.hex_digit(byte)?;
+                self.pos += 1;
+                if self.pos == CHKSUM_LEN {
+                    self.state = State::Eoi;
+                }
+            }
+            State::Eoi => {
+                if byte != Frame::EOI && self.options.missing_eoi {
+                    // Some firmware terminates frames with another character
+                    debug!("Consuming {byte:#04X} in place of EOI");
+                }
+                self.complete(byte == Frame::EOI)?;
+                return Ok(true);
+            }
+            State::Complete => unreachable!("Decoder is reset before processing a byte"),
+        }
+        Ok(false)
+    }
+    /// Validate a hex digit, returns it in uppercase
+    fn hex_digit(&mut self, byte: u8) -> Result<u8, FrameError> {
+        match byte {
+            b'0'..=b'9' | b'A'..=b'F' => Ok(byte),
+            b'a'..=b'f' if self.options.lowercase_hex => {
+                if !self.warnings.lowercase_hex {
+                    warn!("Tolerating lowercase hex digits");
+                    self.warnings.lowercase_hex = true;
+                }
+                Ok(byte.to_ascii_uppercase())
+            }
+            _ => Err(FrameError::InvalidInput),
+        }
+    }
+    /// Check `CHKSUM` and `EOI` of a frame awaiting its `EOI`
+    fn complete(&mut self, eoi: bool) -> Result<(), FrameError> {
+        let mut chksum = [0u8; CHKSUM_LEN];
+        chksum.copy_from_slice(&self.ascii[..CHKSUM_LEN]);
+        check_checksum(
+            &chksum,
+            &mut self.checksum,
+            &self.options,
+            &mut self.warnings,
+        )?;
+        if !eoi {
+            if !self.options.missing_eoi {
+                return Err(FrameError::InvalidInput);
+            }
+            warn!("Tolerating missing EOI");
+            self.warnings.missing_eoi = true;
+        }
+        self.state = State::Complete;
+        Ok(())
+    }
+    /// Discard the current frame after an error (see [DecodeOptions::resync])
+    fn discard(&mut self, e: FrameError) {
+        debug!(
+            "Discarding {} bytes of invalid frame: {e:?}",
+            self.frame_len
+        );
+        self.skipped += self.frame_len;
+        self.reset();
+    }
+    fn info_len(&self) -> usize {
+        self.header.as_ref().map_or(0, Header::info_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameDecoder, FrameError};
+    use crate::{
+        MAX_UNENCODED_PAYLOAD_LEN,
+        frame::{DecodeOptions, DecodeWarnings},
+    };
+
+    /// Example response from specification
+    const PACKET: &[u8] = b"~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\r";
+
+    #[test]
+    fn decode_chunks() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut decoder = FrameDecoder::new(&mut info_buf);
+
+        let mut frames = 0;
+        let stream = [PACKET, PACKET].concat();
+        for mut chunk in stream.chunks(7) {
+            while !chunk.is_empty() {
+                let (consumed, frame) = decoder.push(chunk);
+                chunk = &chunk[consumed..];
+                if let Some(frame) = frame {
+                    let frame = frame.expect("Error decoding packet");
+                    assert_eq!(frame.info.len(), 55);
+                    assert_eq!(frame.adr, 1);
+                    frames += 1;
+                }
+            }
+        }
+        assert_eq!(frames, 2);
+    }
+    #[test]
+    fn recover_after_error() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut decoder = FrameDecoder::new(&mut info_buf);
+
+        let mut corrupted = PACKET.to_vec();
+        corrupted[20] = b'F';
+        let (consumed, frame) = decoder.push(&corrupted);
+        assert_eq!(consumed, corrupted.len());
+        assert_eq!(frame.unwrap().unwrap_err(), FrameError::Checksum);
+
+        let (consumed, frame) = decoder.push(PACKET);
+        assert_eq!(consumed, PACKET.len());
+        assert!(frame.unwrap().is_ok());
+    }
+    #[test]
+    fn resync_skips_garbage() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let options = DecodeOptions {
+            resync: true,
+            ..DecodeOptions::STRICT
+        };
+        let mut decoder = FrameDecoder::with_options(&mut info_buf, options);
+
+        // Line noise, a truncated frame and a frame with a bad checksum
+        let mut corrupted = PACKET.to_vec();
+        corrupted[20] = b'F';
+        let stream = [b"\x00\xFF", &PACKET[..30], &corrupted, PACKET].concat();
+
+        let (consumed, frame) = decoder.push(&stream);
+        assert_eq!(consumed, stream.len());
+        assert!(frame.unwrap().is_ok());
+        assert_eq!(decoder.skipped(), 2 + 30 + corrupted.len());
+
+        let (_, frame) = decoder.push(PACKET);
+        assert!(frame.unwrap().is_ok());
+        assert_eq!(decoder.skipped(), 0);
+    }
+    #[test]
+    fn lenient_tolerances() {
+        // Lowercase hex with checksum off by one, missing EOI and bad LCHKSUM
+        let lowercase = b"~20014600c06e11010f0d450d440d450d440d450d440d3e0d450d4a0d4a0d4b0d4a0d4a0d4a0d4a050bc30bc30bc30bcd0bcd0000c725bf6802c3500002e014\r";
+        let mut bad_length = PACKET.to_vec();
+        bad_length[9] = b'0';
+        bad_length[PACKET.len() - 5..].copy_from_slice(b"E566\r");
+        let cases: [(&[u8], DecodeWarnings); 3] = [
+            (
+                lowercase,
+                DecodeWarnings {
+                    lowercase_hex: true,
+                    checksum_off_by_one: true,
+                    ..DecodeWarnings::default()
+                },
+            ),
+            (
+                &PACKET[..PACKET.len() - 1],
+                DecodeWarnings {
+                    missing_eoi: true,
+                    ..DecodeWarnings::default()
+                },
+            ),
+            (
+                &bad_length,
+                DecodeWarnings {
+                    bad_length_checksum: true,
+                    ..DecodeWarnings::default()
+                },
+            ),
+        ];
+        for (packet, warnings) in cases {
+            let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+            let mut decoder = FrameDecoder::new(&mut info_buf);
+            let (_, frame) = decoder.push(packet);
+            assert!(
+                !matches!(frame, Some(Ok(_))),
+                "Strict decoder accepted {packet:?}"
+            );
+
+            let mut decoder = FrameDecoder::with_options(&mut info_buf, DecodeOptions::LENIENT);
+            let (consumed, frame) = decoder.push(packet);
+            assert_eq!(consumed, packet.len());
+            let frame = match frame {
+                Some(frame) => frame,
+                None => decoder.finish().expect("Frame incomplete"),
+            };
+            assert_eq!(frame.expect("Error decoding packet").info.len(), 55);
+            assert_eq!(decoder.warnings(), warnings);
+        }
+
+        // A frame without EOI is complete once the next frame starts
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut decoder = FrameDecoder::with_options(&mut info_buf, DecodeOptions::LENIENT);
+        let stream = [&PACKET[..PACKET.len() - 1], PACKET].concat();
+        let (consumed, frame) = decoder.push(&stream);
+        assert_eq!(consumed, PACKET.len() - 1);
+        assert!(frame.unwrap().is_ok());
+        let (_, frame) = decoder.push(&stream[consumed..]);
+        assert!(frame.unwrap().is_ok());
+        assert!(decoder.warnings().is_empty());
+    }
+}
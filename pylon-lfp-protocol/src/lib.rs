@@ -1,67 +1,185 @@
 #![cfg_attr(not(test), no_std)]
 use core::fmt::Display;
+use core::marker::PhantomData;
+use core::ops::RangeInclusive;
+use core::time::Duration;
+
+use embedded_hal::delay::DelayNs;
 
 use embedded_io::Read;
+use embedded_io::ReadReady;
 use embedded_io::Write;
 
+#[cfg(feature = "async")]
+pub mod asynch;
+#[cfg(feature = "capture")]
+pub mod capture;
 pub mod commands;
+pub mod detect;
+pub mod dialect;
+#[cfg(feature = "emulator")]
+pub mod emulator;
 mod frame;
+pub mod pace;
+pub mod retry;
+#[cfg(feature = "rfc2217")]
+pub mod rfc2217;
+pub mod rs485;
+pub mod scan;
+pub mod seplos;
+#[cfg(feature = "sniffer")]
+pub mod sniffer;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod timing;
 pub mod types;
 mod util;
 
+#[cfg(feature = "async")]
+pub use asynch::AsyncPylontechBms;
 pub use frame::{
-    Cid2, CommandCode, Frame, InfoLength, MAX_UNENCODED_PAYLOAD_LEN, ResponseCode, Version,
+    Cid1, Cid2, CommandCode, DecodeOptions, DecodeWarnings, Decoded, Frame, FrameDecoder,
+    FrameError, InfoLength, MAX_UNENCODED_PAYLOAD_LEN, ResponseCode, Version,
 };
 use zerocopy::FromZeros;
 use zerocopy::IntoBytes;
 
-use crate::commands::AnalogValueResponse;
 use crate::commands::SystemParameter;
+use crate::dialect::{Dialect, Pylontech, ResponsePayload};
+use crate::retry::RetryPolicy;
+use crate::scan::Scan;
+use crate::timing::{ReadyFn, TimedRead, Timing};
 
 /// Major version this library intends to implement
 const RS232_PROTOCOL_VERSION_MAJOR: u8 = 2;
 /// Minor version this library intends to implement
 const RS232_PROTOCOL_VERSION_MINOR: u8 = 8;
 
+/// Function discarding stale input of a transport
+type DrainFn<U> = fn(&mut U) -> Result<usize, <U as embedded_io::ErrorType>::Error>;
+
 /// Pylontech RS232 protocol BMS
-pub struct PylontechBms<U: Read + Write> {
+///
+/// Generic over the [Dialect] spoken by the BMS, defaults to the [Pylontech] specification.
+/// Waiting (e.g. the backoff of a [RetryPolicy]) requires a delay, see [PylontechBms::with_delay].
+pub struct PylontechBms<U: Read + Write, D: Dialect = Pylontech, T: Wait = NoDelay> {
     uart: U,
+    dialect: PhantomData<D>,
+    delay: T,
+    options: DecodeOptions,
+    /// Drains stale input before a request, see [PylontechBms::drain_before_request]
+    drain: Option<DrainFn<U>>,
+    retry: RetryPolicy,
+    /// Drains input between attempts, see [PylontechBms::set_retry_policy]
+    retry_drain: Option<DrainFn<U>>,
+    timing: Timing,
+    /// Polls input for the timeouts, see [PylontechBms::set_timing]
+    ready: Option<ReadyFn<U>>,
+    /// Bytes discarded during the last request
+    skipped: usize,
+    /// Deviations tolerated in the last response
+    warnings: DecodeWarnings,
 }
 
-impl<U: Read + Write> PylontechBms<U> {
+/// Placeholder of a [PylontechBms] without delay, waiting returns immediately
+///
+/// Doesn't implement [DelayNs], a [Timing] or [RetryPolicy] can only be set with a delay.
+pub struct NoDelay;
+
+/// Waiting of a [PylontechBms], implemented for every [DelayNs] and [NoDelay]
+pub trait Wait {
+    /// Wait for `us` microseconds
+    fn wait_us(&mut self, us: u32);
+}
+impl<T: DelayNs> Wait for T {
+    fn wait_us(&mut self, us: u32) {
+        self.delay_us(us);
+    }
+}
+impl Wait for NoDelay {
+    fn wait_us(&mut self, _us: u32) {}
+}
+
+impl<U: Read + Write, D: Dialect> PylontechBms<U, D> {
     pub fn new(uart: U) -> Self {
-        PylontechBms { uart }
+        PylontechBms {
+            uart,
+            dialect: PhantomData,
+            delay: NoDelay,
+            options: DecodeOptions::default(),
+            drain: None,
+            retry: RetryPolicy::NEVER,
+            retry_drain: None,
+            timing: Timing::NONE,
+            ready: None,
+            skipped: 0,
+            warnings: DecodeWarnings::default(),
+        }
+    }
+    /// Use `delay` for waiting
+    pub fn with_delay<T: DelayNs>(self, delay: T) -> PylontechBms<U, D, T> {
+        PylontechBms {
+            uart: self.uart,
+            dialect: PhantomData,
+            delay,
+            options: self.options,
+            drain: self.drain,
+            retry: self.retry,
+            retry_drain: self.retry_drain,
+            timing: self.timing,
+            ready: self.ready,
+            skipped: self.skipped,
+            warnings: self.warnings,
+        }
+    }
+}
+
+impl<U: Read + Write, D: Dialect, T: Wait> PylontechBms<U, D, T> {
+    /// Return the transport
+    pub fn into_inner(self) -> U {
+        self.uart
+    }
+    /// Set the [DecodeOptions] used for responses
+    pub fn set_decode_options(&mut self, options: DecodeOptions) {
+        self.options = options;
+    }
+    /// Number of bytes discarded during the last request
+    ///
+    /// Counts stale input drained before the request
+    /// and bytes skipped before the response (see [DecodeOptions::resync]).
+    pub fn skipped_bytes(&self) -> usize {
+        self.skipped
+    }
+    /// Deviations from the specification tolerated in the last response
+    ///
+    /// See [DecodeOptions] for the tolerances.
+    pub fn decode_warnings(&self) -> DecodeWarnings {
+        self.warnings
     }
 
     /// Get the protocol version from the BMS
     pub fn get_protocol_version(&mut self) -> Result<Version, Error<U::Error>> {
-        let packet = Frame::new(
-            Version::default(),
-            1,
-            CommandCode::GetProtocolVersion.into(),
-            &[],
-        );
-        packet.encode(&mut self.uart)?;
-        self.uart.flush()?;
         let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN]; // TODO payload might be always 0 length for get version
-        let response = Frame::decode(&mut self.uart, &mut buf)?;
+        let response = self.request(
+            D::frame_address(1),
+            CommandCode::GetProtocolVersion,
+            &[],
+            &mut buf,
+        )?;
         Ok(response.ver)
     }
 
     /// Get the system parameters
     pub fn get_system_parameter(&mut self) -> Result<SystemParameter, Error<U::Error>> {
-        let packet = Frame::new(
-            Version::default(),
-            1,
-            CommandCode::GetSystemParameter.into(),
-            &[],
-        );
-        packet.encode(&mut self.uart)?;
-        self.uart.flush()?;
         let mut system_parameter = SystemParameter::new_zeroed();
 
         let buf = system_parameter.as_mut_bytes();
-        Frame::decode(&mut self.uart, buf)?;
+        self.request(
+            D::frame_address(1),
+            CommandCode::GetSystemParameter,
+            &[],
+            buf,
+        )?;
         Ok(system_parameter)
     }
     /// Get analog values
@@ -75,21 +193,199 @@ impl<U: Read + Write> PylontechBms<U> {
         &mut self,
         address: u8,
         paylaod_buf: &'a mut [u8],
-    ) -> Result<AnalogValueResponse<'a>, Error<U::Error>> {
-        let adr = [address];
-        let packet = Frame::new(
-            Version::default(),
-            1,
-            CommandCode::GetAnalogValue.into(),
-            &adr,
-        );
+    ) -> Result<D::AnalogValue<'a>, Error<U::Error>> {
+        let len = self
+            .request(
+                D::frame_address(address),
+                CommandCode::GetAnalogValue,
+                &[address],
+                paylaod_buf,
+            )?
+            .info
+            .len();
+        parse_payload(&paylaod_buf[..len])
+    }
+    /// Get alarm info
+    ///
+    /// Command "_get alarm info_" to get the alarm states of a battery pack.
+    ///
+    /// Takes a buffer where the dynamically sized response is stored.
+    pub fn get_alarm_info<'a>(
+        &mut self,
+        address: u8,
+        paylaod_buf: &'a mut [u8],
+    ) -> Result<D::AlarmInfo<'a>, Error<U::Error>> {
+        let len = self
+            .request(
+                D::frame_address(address),
+                CommandCode::GetAlarmInfo,
+                &[address],
+                paylaod_buf,
+            )?
+            .info
+            .len();
+        parse_payload(&paylaod_buf[..len])
+    }
+
+    /// Probe the `addresses` for responding packs
+    ///
+    /// Frames are sent to each address directly, bypassing [Dialect::frame_address].
+    /// See [scan::Scan] for the results.
+    pub fn scan(&mut self, addresses: RangeInclusive<u8>) -> Scan<'_, U, D, T> {
+        Scan::new(self, addresses)
+    }
+
+    /// Send a command and receive the response, retrying according to the [RetryPolicy]
+    ///
+    /// Returns [Error::UnsupportedCommand] if the [Dialect] doesn't implement `command`
+    /// and [Error::UnsupportedControlIdentifier] if the response doesn't match the dialects `CID1`.
+    fn request<'a>(
+        &mut self,
+        adr: u8,
+        command: CommandCode,
+        info: &[u8],
+        payload_buf: &'a mut [u8],
+    ) -> Result<Frame<'a>, Error<U::Error>> {
+        if !D::supports(command) {
+            return Err(Error::UnsupportedCommand);
+        }
+        self.skipped = 0;
+        let mut attempt = 1;
+        // The frame of an attempt can't outlive the loop, it's rebuilt from its header
+        let (ver, adr, cid1, cid2, len) = loop {
+            match self.attempt(adr, command, info, payload_buf) {
+                Ok(frame) => {
+                    break (
+                        frame.ver,
+                        frame.adr,
+                        frame.cid1,
+                        frame.cid2,
+                        frame.info.len(),
+                    );
+                }
+                Err(e) if attempt < self.retry.max_attempts && self.retry.is_retryable(&e) => {
+                    log::debug!("Retrying {command:?} after {e}");
+                    self.wait(self.retry.backoff(attempt));
+                    if let Some(drain) = self.retry_drain {
+                        self.skipped += drain(&mut self.uart)?;
+                    }
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        Ok(Frame::new_with_cid1(
+            ver,
+            adr,
+            cid1,
+            cid2,
+            &payload_buf[..len],
+        ))
+    }
+
+    /// Wait for `duration` using the delay
+    fn wait(&mut self, duration: Duration) {
+        if !duration.is_zero() {
+            let us = duration.as_micros().min(u32::MAX as u128) as u32;
+            self.delay.wait_us(us);
+        }
+    }
+
+    /// Send a command and receive the response once
+    fn attempt<'a>(
+        &mut self,
+        adr: u8,
+        command: CommandCode,
+        info: &[u8],
+        payload_buf: &'a mut [u8],
+    ) -> Result<Frame<'a>, Error<U::Error>> {
+        self.wait(self.timing.inter_frame_gap);
+        if let Some(drain) = self.drain {
+            self.skipped += drain(&mut self.uart)?;
+        }
+        let packet = Frame::new_with_cid1(D::VERSION, adr, D::CID1, command.into(), info);
         packet.encode(&mut self.uart)?;
         self.uart.flush()?;
+        self.wait(self.timing.turnaround);
+
+        let decoded = match self.ready {
+            Some(ready) if self.timing.polls() => {
+                let mut reader =
+                    TimedRead::new(&mut self.uart, &mut self.delay, ready, &self.timing);
+                let decoded = Frame::decode_with(&mut reader, payload_buf, self.options);
+                match decoded {
+                    Err(_) if reader.timed_out => Err(Error::Timeout),
+                    decoded => decoded,
+                }
+            }
+            _ => Frame::decode_with(&mut self.uart, payload_buf, self.options),
+        }?;
+        self.skipped += decoded.skipped;
+        self.warnings = decoded.warnings;
+        let response = decoded.frame;
+        if response.cid1 != D::CID1 {
+            return Err(Error::UnsupportedControlIdentifier);
+        }
+        Ok(response)
+    }
+}
 
-        Frame::decode(&mut self.uart, paylaod_buf)?;
-        let measurements = AnalogValueResponse::from_bytes(paylaod_buf)?;
-        Ok(measurements)
+impl<U: Read + Write + ReadReady, D: Dialect, T: Wait> PylontechBms<U, D, T> {
+    /// Discard all input received so far
+    ///
+    /// Returns the number of bytes discarded.
+    pub fn drain(&mut self) -> Result<usize, Error<U::Error>> {
+        Ok(drain(&mut self.uart)?)
     }
+    /// Discard stale input (e.g. a late response to a timed out request) before each request
+    pub fn drain_before_request(&mut self, enabled: bool) {
+        self.drain = if enabled { Some(drain::<U>) } else { None };
+    }
+}
+
+impl<U: Read + Write + ReadReady, D: Dialect, T: DelayNs> PylontechBms<U, D, T> {
+    /// Set the [RetryPolicy] for requests failing on transient errors
+    ///
+    /// Input is discarded between attempts, a late response would be mistaken for the next one.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+        self.retry_drain = Some(drain::<U>);
+    }
+    /// Set the [Timing] of requests
+    ///
+    /// Timeouts are measured by polling for input and waiting with the delay
+    /// (see [PylontechBms::with_delay]).
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.ready = Some(U::read_ready);
+    }
+}
+
+/// Read until no more input is ready, returns the number of bytes read
+fn drain<U: Read + ReadReady>(uart: &mut U) -> Result<usize, U::Error> {
+    let mut buf = [0u8; 32];
+    let mut drained = 0;
+    while uart.read_ready()? {
+        let read = uart.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        drained += read;
+    }
+    if drained > 0 {
+        log::debug!("Drained {drained} bytes of stale input");
+    }
+    Ok(drained)
+}
+
+/// Parse a response payload, mapping parse errors to [Error::InvalidInput]
+fn parse_payload<'a, P: ResponsePayload<'a>, T: embedded_io::Error>(
+    buf: &'a [u8],
+) -> Result<P, Error<T>> {
+    P::from_payload(buf).map_err(|e| {
+        log::error!("Failed to parse response payload: {e:?}");
+        Error::InvalidInput
+    })
 }
 
 #[derive(Debug)]
@@ -113,6 +409,10 @@ pub enum Error<T: embedded_io::Error> {
     /// This might be due to protocol version mismatch or
     /// misbehaving BMS.
     UnsupportedControlIdentifier,
+    /// Command isn't supported by the [Dialect]
+    UnsupportedCommand,
+    /// No response or silence within a response exceeding the [Timing]
+    Timeout,
 }
 
 impl<T: embedded_io::Error> Display for Error<T> {
@@ -124,6 +424,8 @@ impl<T: embedded_io::Error> Display for Error<T> {
             Error::InvalidInput => write!(f, "Invalid input"),
             Error::Cecksum => write!(f, "Checksum error"),
             Error::UnsupportedControlIdentifier => write!(f, "Unsupported control identifier"),
+            Error::UnsupportedCommand => write!(f, "Unsupported command"),
+            Error::Timeout => write!(f, "Timeout"),
         }
     }
 }
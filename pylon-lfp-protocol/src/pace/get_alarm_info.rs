@@ -0,0 +1,111 @@
+use core::ops::Deref;
+
+use crate::{
+    commands::{AlarmInfo, AlarmInfoParseError},
+    dialect::ResponsePayload,
+};
+
+/// Response payload of a Pace "_get alarm info_" command
+///
+/// Pace boards append nine user-defined status bytes to the alarm states:
+/// protect, instruction, control, fault, balance and warn status words.
+/// Firmware reporting fewer status bytes leaves the missing words at [None].
+#[derive(Debug)]
+pub struct PaceAlarmInfo<'a>(AlarmInfo<'a>);
+
+impl<'a> PaceAlarmInfo<'a> {
+    /// Offset of "_protect status 1_" in the user-defined status bytes
+    const PROTECT_STATUS_1: usize = 0;
+    /// Offset of "_instruction status_" in the user-defined status bytes
+    const INSTRUCTION_STATUS: usize = 2;
+    /// Offset of "_control status_" in the user-defined status bytes
+    const CONTROL_STATUS: usize = 3;
+    /// Offset of "_fault status_" in the user-defined status bytes
+    const FAULT_STATUS: usize = 4;
+    /// Offset of "_balance status_" in the user-defined status bytes
+    const BALANCE_STATUS: usize = 5;
+    /// Offset of "_warn status 1_" in the user-defined status bytes
+    const WARN_STATUS_1: usize = 7;
+
+    pub fn from_bytes(buf: &'a [u8]) -> Result<PaceAlarmInfo<'a>, AlarmInfoParseError> {
+        AlarmInfo::from_bytes(buf).map(PaceAlarmInfo)
+    }
+    /// Protect status `1` or `2`
+    pub fn protect_status(&self, n: u8) -> Option<u8> {
+        match n {
+            1..=2 => self.status_u8(Self::PROTECT_STATUS_1 + n as usize - 1),
+            _ => None,
+        }
+    }
+    /// Instruction status (current limit, charge and discharge MOSFETs)
+    pub fn instruction_status(&self) -> Option<u8> {
+        self.status_u8(Self::INSTRUCTION_STATUS)
+    }
+    /// Control status
+    pub fn control_status(&self) -> Option<u8> {
+        self.status_u8(Self::CONTROL_STATUS)
+    }
+    /// Fault status
+    pub fn fault_status(&self) -> Option<u8> {
+        self.status_u8(Self::FAULT_STATUS)
+    }
+    /// Balancing state, one bit per cell
+    pub fn balance_status(&self) -> Option<u16> {
+        self.status_u16(Self::BALANCE_STATUS)
+    }
+    /// Warn status `1` or `2`
+    pub fn warn_status(&self, n: u8) -> Option<u8> {
+        match n {
+            1..=2 => self.status_u8(Self::WARN_STATUS_1 + n as usize - 1),
+            _ => None,
+        }
+    }
+}
+impl<'a> Deref for PaceAlarmInfo<'a> {
+    type Target = AlarmInfo<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a> ResponsePayload<'a> for PaceAlarmInfo<'a> {
+    type Error = AlarmInfoParseError;
+
+    fn from_payload(buf: &'a [u8]) -> Result<Self, Self::Error> {
+        PaceAlarmInfo::from_bytes(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PaceAlarmInfo;
+    use crate::{Frame, MAX_UNENCODED_PAYLOAD_LEN, types::AlarmState};
+
+    /// Alarm info response of a 16 cell Pace pack
+    const PACKET: &[u8] = b"~25014600E04E000110000000000000000000000000000000020600000000000100000009000100030000040000EEC4\r";
+
+    #[test]
+    fn parse_pace_alarm_info() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut packet = PACKET;
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+
+        let alarms = PaceAlarmInfo::from_bytes(frame.info).expect("Failed to parse payload");
+        assert_eq!(alarms.cell_voltage_alarms.len(), 16);
+        assert_eq!(
+            alarms.cell_voltage_alarms[15],
+            AlarmState::ABOVE_UPPER_LIMIT
+        );
+        assert_eq!(alarms.temperature_alarms[5], AlarmState::BELOW_LOWER_LIMIT);
+        assert_eq!(alarms.protect_status(1), Some(0x00));
+        assert_eq!(alarms.protect_status(2), Some(0x01));
+        assert_eq!(alarms.protect_status(3), None);
+        assert_eq!(alarms.instruction_status(), Some(0x00));
+        assert_eq!(alarms.control_status(), Some(0x03));
+        assert_eq!(alarms.fault_status(), Some(0x00));
+        assert_eq!(alarms.balance_status(), Some(0x0004));
+        assert_eq!(alarms.warn_status(1), Some(0x00));
+        assert_eq!(alarms.warn_status(2), Some(0x00));
+    }
+}
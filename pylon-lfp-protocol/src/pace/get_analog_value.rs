@@ -0,0 +1,147 @@
+use zerocopy::{FromBytes, Immutable, KnownLayout};
+
+use crate::{
+    commands::{AnalogValueParseError, AnalogValueResponse, PackData},
+    dialect::ResponsePayload,
+    types::{
+        AmpereHours, ChangeFlags, Percent, Temperature, Volt,
+        exponents::{CENTI, DECI, MILLI},
+    },
+};
+
+/// [PackData] with the exponents used by Pace BMS
+///
+/// Currents and capacities are reported in 10 mA and 10 mAh.
+pub type PacePackDataRaw<'a> = PackData<'a, MILLI, MILLI, CENTI, CENTI, DECI>;
+
+/// Response payload of a Pace "_get analog value_" command
+#[derive(Debug)]
+pub struct PaceAnalogValue<'a> {
+    response: AnalogValueResponse<'a>,
+}
+
+impl<'a> PaceAnalogValue<'a> {
+    pub fn from_bytes(buf: &'a [u8]) -> Result<PaceAnalogValue<'a>, AnalogValueParseError> {
+        let response = AnalogValueResponse::from_bytes(buf)?;
+        Ok(PaceAnalogValue { response })
+    }
+    pub fn flags(&self) -> &ChangeFlags {
+        &self.response.flags
+    }
+    /// Get the number of packs reported by this response
+    pub fn get_pack_count(&self) -> u8 {
+        self.response.get_pack_count()
+    }
+    /// Get [PacePackData] by number
+    ///
+    /// Indexed starting at `0`.
+    pub fn get_pack(&self, pack_number: u8) -> Result<PacePackData<'_>, AnalogValueParseError> {
+        let pack = self.response.get_pack(pack_number)?;
+        Ok(PacePackData { pack })
+    }
+}
+
+/// Measurement data for a pack returned by a Pace "_get analog value_" command
+///
+/// The measurements shared with the Pylontech layout are available through [PacePackData::pack].
+/// The additional user-defined fields return [None] if the BMS didn't report them.
+#[derive(Debug)]
+pub struct PacePackData<'a> {
+    pub pack: PacePackDataRaw<'a>,
+}
+
+impl<'a> PacePackData<'a> {
+    /// Index of the MOSFET temperature in the reported temperatures
+    const MOSFET_TEMPERATURE: usize = 4;
+    /// Index of the environment temperature in the reported temperatures
+    const ENVIRONMENT_TEMPERATURE: usize = 5;
+
+    /// Design capacity of the pack
+    pub fn design_capacity(&self) -> Option<&'a AmpereHours<CENTI>> {
+        self.user_defined_field(0)
+    }
+    /// State of charge
+    pub fn state_of_charge(&self) -> Option<&'a Percent<0>> {
+        self.user_defined_field(1)
+    }
+    /// State of health
+    pub fn state_of_health(&self) -> Option<&'a Percent<0>> {
+        self.user_defined_field(2)
+    }
+    /// Voltage at the pack terminals
+    pub fn port_voltage(&self) -> Option<&'a Volt<MILLI>> {
+        self.user_defined_field(3)
+    }
+    /// Temperatures of the cell sensors
+    ///
+    /// Pace boards report the cell temperatures first,
+    /// followed by the MOSFET and environment temperature.
+    pub fn cell_temperatures(&self) -> &'a [Temperature<DECI>] {
+        let temperatures = self.pack.temperatures;
+        &temperatures[..temperatures.len().min(Self::MOSFET_TEMPERATURE)]
+    }
+    /// Temperature of the MOSFETs
+    pub fn mosfet_temperature(&self) -> Option<&'a Temperature<DECI>> {
+        self.pack.temperatures.get(Self::MOSFET_TEMPERATURE)
+    }
+    /// Temperature of the environment
+    pub fn environment_temperature(&self) -> Option<&'a Temperature<DECI>> {
+        self.pack.temperatures.get(Self::ENVIRONMENT_TEMPERATURE)
+    }
+    fn user_defined_field<T: FromBytes + KnownLayout + Immutable>(
+        &self,
+        index: usize,
+    ) -> Option<&'a T> {
+        let data = self.pack.user_defined_data();
+        let bytes = data.get(index * 2..index * 2 + 2)?;
+        T::ref_from_bytes(bytes).ok()
+    }
+}
+
+impl<'a> ResponsePayload<'a> for PaceAnalogValue<'a> {
+    type Error = AnalogValueParseError;
+
+    fn from_payload(buf: &'a [u8]) -> Result<Self, Self::Error> {
+        PaceAnalogValue::from_bytes(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PaceAnalogValue;
+    use crate::{Frame, MAX_UNENCODED_PAYLOAD_LEN};
+
+    /// Analog value response of two 16 cell Pace packs
+    const PACKET: &[u8] = b"~2501460071080002100CE40CE50CE60CE70CE80CE90CEA0CEB0CEC0CED0CEE0CEF0CF00CF10CF20CF3060BA50BA60BA70BA80BB90BAFFF06CF0827100627100010271000640064CF1C100CEE0CEF0CF00CF10CF20CF30CF40CF50CF60CF70CF80CF90CFA0CFB0CFC0CFD060BA50BA60BA70BA80BB90BAF0078CF0827100627100010271000630064CF1CC212\r";
+
+    #[test]
+    fn parse_pace_analog_value() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut packet = PACKET;
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+
+        let analog = PaceAnalogValue::from_bytes(frame.info).expect("Failed to parse payload");
+        assert_eq!(analog.get_pack_count(), 2);
+
+        let pack = analog.get_pack(0).expect("Failed to parse pack 0");
+        assert_eq!(pack.pack.cell_voltages.len(), 16);
+        assert_eq!(pack.pack.pack_current.get_ampere(), -2.5);
+        assert_eq!(pack.pack.pack_voltage.get_raw(), 53_000);
+        assert_eq!(pack.pack.pack_remaining.get_ampere_hours(), 100.0);
+        assert_eq!(pack.pack.user_defined, 6);
+        assert_eq!(pack.pack.cell_cycles, 16);
+        assert_eq!(pack.design_capacity().unwrap().get_raw(), 10_000);
+        assert_eq!(pack.state_of_charge().unwrap().get_percent(), 100.0);
+        assert_eq!(pack.state_of_health().unwrap().get_percent(), 100.0);
+        assert_eq!(pack.port_voltage().unwrap().get_raw(), 53_020);
+        assert_eq!(pack.cell_temperatures().len(), 4);
+        assert_eq!(pack.mosfet_temperature().unwrap().get_raw(), 3001);
+        assert_eq!(pack.environment_temperature().unwrap().get_raw(), 2991);
+
+        // The additional fields of the first pack have to be skipped
+        let pack = analog.get_pack(1).expect("Failed to parse pack 1");
+        assert_eq!(pack.pack.cell_voltages[0].get_raw(), 3310);
+        assert_eq!(pack.pack.pack_current.get_raw(), 120);
+        assert_eq!(pack.state_of_charge().unwrap().get_raw(), 99);
+    }
+}
@@ -0,0 +1,59 @@
+//! Pace BMS protocol dialect
+//!
+//! Pace based packs (sold under many brands as "_Pylontech compatible_")
+//! speak a Pylontech derived protocol version 2.5.
+//! Frames use the Pylontech `CID1` ([Cid1::BATTERY_DATA]), but analog values
+//! carry additional user-defined fields and alarm info carries additional status words.
+
+mod get_alarm_info;
+mod get_analog_value;
+
+pub use get_alarm_info::*;
+pub use get_analog_value::*;
+
+use crate::{
+    Cid1, CommandCode, PylontechBms, Version,
+    dialect::Dialect,
+    types::{
+        ScalingProfile,
+        exponents::{CENTI, DECI, MILLI},
+    },
+};
+
+/// Protocol version spoken by Pace BMS
+const PACE_PROTOCOL_VERSION_MAJOR: u8 = 2;
+/// Protocol minor version spoken by Pace BMS
+const PACE_PROTOCOL_VERSION_MINOR: u8 = 5;
+
+/// Pace BMS dialect
+///
+/// Each pack of a stack answers on the `ADR` set by its DIP switches,
+/// requests repeat that address in their command info.
+pub struct Pace;
+
+impl Dialect for Pace {
+    const CID1: Cid1 = Cid1::BATTERY_DATA;
+    const VERSION: Version = Version::new(PACE_PROTOCOL_VERSION_MAJOR, PACE_PROTOCOL_VERSION_MINOR);
+    const SCALING: ScalingProfile = ScalingProfile {
+        cell_voltage: MILLI,
+        total_voltage: MILLI,
+        current: CENTI,
+        amp_hours: CENTI,
+        temperature: DECI,
+    };
+
+    type AnalogValue<'a> = PaceAnalogValue<'a>;
+    type AlarmInfo<'a> = PaceAlarmInfo<'a>;
+
+    fn supports(command: CommandCode) -> bool {
+        matches!(
+            command,
+            CommandCode::GetProtocolVersion
+                | CommandCode::GetAnalogValue
+                | CommandCode::GetAlarmInfo
+        )
+    }
+}
+
+/// Pace BMS
+pub type PaceBms<U> = PylontechBms<U, Pace>;
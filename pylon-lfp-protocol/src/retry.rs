@@ -0,0 +1,159 @@
+//! Retrying requests failing on transient errors
+//!
+//! Flaky cabling or collisions on a bus corrupt single frames,
+//! a [RetryPolicy] repeats such requests instead of failing:
+//!
+//! ```rust
+//! # fn configure<U: embedded_io::Read + embedded_io::Write + embedded_io::ReadReady>(
+//! #     uart: U,
+//! #     delay: impl embedded_hal::delay::DelayNs,
+//! # ) {
+//! use pylon_lfp_protocol::{PylontechBms, retry::RetryPolicy};
+//!
+//! let bms: PylontechBms<_> = PylontechBms::new(uart);
+//! let mut bms = bms.with_delay(delay);
+//! bms.set_retry_policy(RetryPolicy {
+//!     max_attempts: 5,
+//!     ..RetryPolicy::TRANSIENT
+//! });
+//! # }
+//! ```
+
+use core::time::Duration;
+
+use embedded_io::ErrorKind;
+
+use crate::{Error, ResponseCode};
+
+/// Which failed requests are repeated and how often
+///
+/// [RetryPolicy::NEVER] is the default.
+/// The backoff between attempts requires a delay, see [PylontechBms::with_delay](crate::PylontechBms::with_delay).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per request including the first, `1` disables retrying
+    pub max_attempts: u8,
+    /// Delay before the first retry
+    pub backoff: Duration,
+    /// Factor the delay grows by with every further retry
+    pub backoff_factor: u32,
+    /// Retry if the transport or the [Timing](crate::timing::Timing) timed out
+    pub retry_timeout: bool,
+    /// Retry if the response was corrupted (see [Error::InvalidInput] and [Error::Cecksum])
+    pub retry_invalid: bool,
+    /// Error codes signaled by the BMS that are retried
+    pub retry_responses: &'static [ResponseCode],
+}
+
+impl RetryPolicy {
+    /// Fail on the first error
+    pub const NEVER: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        backoff: Duration::ZERO,
+        backoff_factor: 1,
+        retry_timeout: false,
+        retry_invalid: false,
+        retry_responses: &[],
+    };
+    /// Retry errors caused by line noise up to two times
+    ///
+    /// Timeouts, corrupted responses and the checksum and communication errors signaled by the BMS
+    /// are retried. Errors caused by the request itself (e.g. [ResponseCode::Cid2Err]) are not.
+    pub const TRANSIENT: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        backoff: Duration::from_millis(100),
+        backoff_factor: 2,
+        retry_timeout: true,
+        retry_invalid: true,
+        retry_responses: &[
+            ResponseCode::ChksumErr,
+            ResponseCode::LChksumErr,
+            ResponseCode::CommunicationErr,
+        ],
+    };
+
+    /// Whether `error` is worth another attempt
+    ///
+    /// Transport errors other than timeouts are never retried.
+    pub fn is_retryable<T: embedded_io::Error>(&self, error: &Error<T>) -> bool {
+        match error {
+            Error::Response(code) => self.retry_responses.contains(code),
+            Error::Transport(e) => self.retry_timeout && e.kind() == ErrorKind::TimedOut,
+            Error::Timeout => self.retry_timeout,
+            Error::InvalidInput | Error::Cecksum => self.retry_invalid,
+            _ => false,
+        }
+    }
+    /// Delay before retry number `retry` (starting at `1`)
+    pub fn backoff(&self, retry: u8) -> Duration {
+        let factor = self
+            .backoff_factor
+            .saturating_pow(retry.saturating_sub(1).into());
+        self.backoff.saturating_mul(factor)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::NEVER
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::RetryPolicy;
+    use crate::{
+        Error, MAX_UNENCODED_PAYLOAD_LEN, PylontechBms, ResponseCode,
+        commands::Request,
+        dialect::Pylontech,
+        testing::{EncodedFrame, Exchange, MockTransport, RecordingDelay},
+    };
+    use embedded_io::ErrorKind;
+
+    #[test]
+    fn classify_errors() {
+        let policy = RetryPolicy::TRANSIENT;
+        assert!(policy.is_retryable(&Error::Transport(ErrorKind::TimedOut)));
+        assert!(!policy.is_retryable(&Error::Transport(ErrorKind::BrokenPipe)));
+        assert!(policy.is_retryable(&Error::<ErrorKind>::Response(ResponseCode::ChksumErr)));
+        assert!(!policy.is_retryable(&Error::<ErrorKind>::Response(ResponseCode::Cid2Err)));
+        assert!(policy.is_retryable(&Error::<ErrorKind>::InvalidInput));
+        assert!(!RetryPolicy::NEVER.is_retryable(&Error::Transport(ErrorKind::TimedOut)));
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn retry_transient_errors() {
+        let request =
+            EncodedFrame::request::<Pylontech>(&Request::GetAnalogValue { pack_address: 1 });
+        let chksum_err = EncodedFrame::error::<Pylontech>(1, ResponseCode::ChksumErr);
+        let cid2_err = EncodedFrame::error::<Pylontech>(1, ResponseCode::Cid2Err);
+        let response = b"~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\r";
+        let script = [
+            Exchange::no_response(&request),
+            Exchange::new(&request, b"~2001460"),
+            Exchange::new(&request, &chksum_err),
+            Exchange::new(&request, response),
+            Exchange::new(&request, &cid2_err),
+        ];
+        let mut delay = RecordingDelay::default();
+        let bms: PylontechBms<_> = PylontechBms::new(MockTransport::new(&script));
+        let mut bms = bms.with_delay(&mut delay);
+        bms.set_retry_policy(RetryPolicy {
+            max_attempts: 4,
+            ..RetryPolicy::TRANSIENT
+        });
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let measurements = bms.get_analog_value(1, &mut buf).unwrap();
+        assert_eq!(measurements.get_pack_count(), 1);
+        assert!(matches!(
+            bms.get_analog_value(1, &mut buf),
+            Err(Error::Response(ResponseCode::Cid2Err))
+        ));
+        bms.into_inner().assert_done();
+        assert_eq!(delay.delays(), [100_000, 200_000, 400_000]);
+    }
+}
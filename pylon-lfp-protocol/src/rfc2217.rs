@@ -0,0 +1,418 @@
+//! RFC 2217 (Telnet COM port control) client transport
+//!
+//! Serial servers speaking RFC 2217 forward a serial line over a Telnet connection
+//! and let the client configure the line remotely. [Rfc2217] wraps the connection
+//! (e.g. a TCP stream) and passes the data of the serial line:
+//!
+//! ```rust
+//! # fn connect<T: embedded_io::Read + embedded_io::Write>(tcp: T) -> Result<(), T::Error> {
+//! use pylon_lfp_protocol::{
+//!     PylontechBms,
+//!     rfc2217::{Parity, Rfc2217, StopBits},
+//! };
+//!
+//! let mut port = Rfc2217::new(tcp)?;
+//! port.set_baud_rate(115200)?;
+//! port.set_data_bits(8)?;
+//! port.set_parity(Parity::None)?;
+//! port.set_stop_bits(StopBits::One)?;
+//! let mut bms: PylontechBms<_> = PylontechBms::new(port);
+//! # Ok(())
+//! # }
+//! ```
+
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+
+/// Interpret as command
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+/// Begin of subnegotiation
+const SB: u8 = 250;
+/// End of subnegotiation
+const SE: u8 = 240;
+
+/// Telnet option transmitting all 8 bits
+const BINARY: u8 = 0;
+/// Telnet option suppressing go ahead
+const SUPPRESS_GO_AHEAD: u8 = 3;
+/// Telnet option of RFC 2217
+const COM_PORT_OPTION: u8 = 44;
+
+/// Commands of the COM port option, the server answers with the command plus [SERVER_OFFSET]
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const PURGE_DATA: u8 = 12;
+const SERVER_OFFSET: u8 = 100;
+
+/// Length of the longest subnegotiation kept (`SET-BAUDRATE` response)
+const SUB_LEN: usize = 6;
+/// Length of the input read ahead to check for ready data
+const READY_LEN: usize = 32;
+
+/// Parity of the serial line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None = 1,
+    Odd = 2,
+    Even = 3,
+    Mark = 4,
+    Space = 5,
+}
+
+/// Stop bits of the serial line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One = 1,
+    Two = 2,
+    OnePointFive = 3,
+}
+
+/// Position in the Telnet stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Data,
+    /// After `IAC`
+    Command,
+    /// After `IAC` and the negotiation command, waiting for the option
+    Option(u8),
+    /// Within a subnegotiation
+    Sub,
+    /// After `IAC` within a subnegotiation
+    SubCommand,
+}
+
+/// Client side of a RFC 2217 connection
+///
+/// Passes the data of the serial line, escaping and removing Telnet commands.
+/// Options requested by the server besides binary transmission and the COM port option are refused.
+/// The settings of the line are confirmed asynchronously by the server,
+/// the confirmation is processed while reading.
+pub struct Rfc2217<T> {
+    inner: T,
+    state: State,
+    sub: [u8; SUB_LEN],
+    sub_len: usize,
+    baud_rate: Option<u32>,
+    /// Data read ahead by [ReadReady::read_ready], `ready[ready_pos..ready_len]` is pending
+    ready: [u8; READY_LEN],
+    ready_pos: usize,
+    ready_len: usize,
+}
+
+impl<T: Read + Write> Rfc2217<T> {
+    /// Wrap the connection to a RFC 2217 server and offer the COM port option
+    pub fn new(mut inner: T) -> Result<Self, T::Error> {
+        inner.write_all(&[
+            IAC,
+            WILL,
+            COM_PORT_OPTION,
+            IAC,
+            WILL,
+            BINARY,
+            IAC,
+            DO,
+            BINARY,
+        ])?;
+        inner.flush()?;
+        Ok(Rfc2217 {
+            inner,
+            state: State::Data,
+            sub: [0; SUB_LEN],
+            sub_len: 0,
+            baud_rate: None,
+            ready: [0; READY_LEN],
+            ready_pos: 0,
+            ready_len: 0,
+        })
+    }
+    /// Return the connection
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+    /// Baud rate the server confirmed, `None` until confirmed
+    pub fn baud_rate(&self) -> Option<u32> {
+        self.baud_rate
+    }
+    /// Set the baud rate of the serial line
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), T::Error> {
+        self.baud_rate = None;
+        self.com_port_command(SET_BAUDRATE, &baud_rate.to_be_bytes())
+    }
+    /// Set the number of data bits (`5` to `8`)
+    pub fn set_data_bits(&mut self, data_bits: u8) -> Result<(), T::Error> {
+        self.com_port_command(SET_DATASIZE, &[data_bits])
+    }
+    /// Set the parity of the serial line
+    pub fn set_parity(&mut self, parity: Parity) -> Result<(), T::Error> {
+        self.com_port_command(SET_PARITY, &[parity as u8])
+    }
+    /// Set the stop bits of the serial line
+    pub fn set_stop_bits(&mut self, stop_bits: StopBits) -> Result<(), T::Error> {
+        self.com_port_command(SET_STOPSIZE, &[stop_bits as u8])
+    }
+    /// Discard the data buffered by the server in both directions
+    pub fn purge(&mut self) -> Result<(), T::Error> {
+        self.com_port_command(PURGE_DATA, &[3])
+    }
+
+    /// Send a subnegotiation of the COM port option
+    fn com_port_command(&mut self, command: u8, value: &[u8]) -> Result<(), T::Error> {
+        self.inner.write_all(&[IAC, SB, COM_PORT_OPTION, command])?;
+        for byte in value {
+            match *byte {
+                IAC => self.inner.write_all(&[IAC, IAC])?,
+                byte => self.inner.write_all(&[byte])?,
+            }
+        }
+        self.inner.write_all(&[IAC, SE])?;
+        self.inner.flush()
+    }
+    /// Answer an option negotiation of the server
+    fn negotiate(&mut self, command: u8, option: u8) -> Result<(), T::Error> {
+        let reply = match (command, option) {
+            // Acknowledgements of the options offered by `new`
+            (DO, COM_PORT_OPTION | BINARY) | (WILL, BINARY) => return Ok(()),
+            (WILL, SUPPRESS_GO_AHEAD) => DO,
+            (DO, _) => WONT,
+            (WILL, _) => DONT,
+            _ => return Ok(()),
+        };
+        self.inner.write_all(&[IAC, reply, option])?;
+        self.inner.flush()
+    }
+    /// Process a complete subnegotiation
+    fn subnegotiation(&mut self) {
+        if let [COM_PORT_OPTION, command, value @ ..] = &self.sub[..self.sub_len]
+            && *command == SET_BAUDRATE + SERVER_OFFSET
+            && let Ok(baud_rate) = value.try_into()
+        {
+            self.baud_rate = Some(u32::from_be_bytes(baud_rate));
+        }
+    }
+    /// Remove the Telnet commands from `buf`, returns the length of the remaining data
+    fn filter(&mut self, buf: &mut [u8]) -> Result<usize, T::Error> {
+        let mut len = 0;
+        for i in 0..buf.len() {
+            let byte = buf[i];
+            self.state = match (self.state, byte) {
+                (State::Data, IAC) => State::Command,
+                (State::Data, _) => {
+                    buf[len] = byte;
+                    len += 1;
+                    State::Data
+                }
+                // Escaped data byte
+                (State::Command, IAC) => {
+                    buf[len] = IAC;
+                    len += 1;
+                    State::Data
+                }
+                (State::Command, DO | DONT | WILL | WONT) => State::Option(byte),
+                (State::Command, SB) => {
+                    self.sub_len = 0;
+                    State::Sub
+                }
+                // Other commands (e.g. `NOP`) have no arguments
+                (State::Command, _) => State::Data,
+                (State::Option(command), option) => {
+                    self.negotiate(command, option)?;
+                    State::Data
+                }
+                (State::Sub, IAC) => State::SubCommand,
+                (State::Sub, _) | (State::SubCommand, IAC) => {
+                    // Longer subnegotiations are of no interest, keep their start
+                    if self.sub_len < SUB_LEN {
+                        self.sub[self.sub_len] = byte;
+                        self.sub_len += 1;
+                    }
+                    State::Sub
+                }
+                (State::SubCommand, SE) => {
+                    self.subnegotiation();
+                    State::Data
+                }
+                (State::SubCommand, _) => State::Data,
+            };
+        }
+        Ok(len)
+    }
+}
+
+impl<T: ErrorType> ErrorType for Rfc2217<T> {
+    type Error = T::Error;
+}
+
+impl<T: Read + Write> Read for Rfc2217<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.ready_pos < self.ready_len {
+            let pending = &self.ready[self.ready_pos..self.ready_len];
+            let len = pending.len().min(buf.len());
+            buf[..len].copy_from_slice(&pending[..len]);
+            self.ready_pos += len;
+            return Ok(len);
+        }
+        // Reading only Telnet commands doesn't end the input
+        loop {
+            let read = self.inner.read(buf)?;
+            if read == 0 {
+                return Ok(0);
+            }
+            let len = self.filter(&mut buf[..read])?;
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+impl<T: Read + ReadReady + Write> ReadReady for Rfc2217<T> {
+    /// Whether data is ready, ready Telnet commands are processed
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        if self.ready_pos < self.ready_len {
+            return Ok(true);
+        }
+        let mut ready = [0; READY_LEN];
+        while self.inner.read_ready()? {
+            let read = self.inner.read(&mut ready)?;
+            // The end of the input is ready to be read as well
+            if read == 0 {
+                return Ok(true);
+            }
+            let len = self.filter(&mut ready[..read])?;
+            if len > 0 {
+                self.ready[..len].copy_from_slice(&ready[..len]);
+                self.ready_pos = 0;
+                self.ready_len = len;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<T: Read + Write> Write for Rfc2217<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        match buf.iter().position(|byte| *byte == IAC) {
+            Some(0) => {
+                self.inner.write_all(&[IAC, IAC])?;
+                Ok(1)
+            }
+            Some(end) => self.inner.write(&buf[..end]),
+            None => self.inner.write(buf),
+        }
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use embedded_io::{ErrorType, Read, ReadReady, Write};
+
+    use super::{Parity, Rfc2217};
+    use crate::PylontechBms;
+
+    /// Connection reading from a slice and recording the written bytes
+    struct Connection {
+        input: &'static [u8],
+        output: Vec<u8>,
+        /// Whether the input ends after `input`, reading further blocks (panics) otherwise
+        ends: bool,
+    }
+    impl ErrorType for Connection {
+        type Error = Infallible;
+    }
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            assert!(self.ends || !self.input.is_empty(), "Read would block");
+            self.input.read(buf)
+        }
+    }
+    impl ReadReady for Connection {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(self.ends || !self.input.is_empty())
+        }
+    }
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn negotiate_and_pass_data() {
+        let connection = Connection {
+            // Acknowledgement, offer of "suppress go ahead" and "echo",
+            // data with an escaped `0xFF`, baud rate confirmation and more data
+            input: b"\xff\xfd\x2c\xff\xfb\x03\xff\xfb\x01~20\xff\xff\xff\xfa\x2c\x65\x00\x00\x25\x80\xff\xf0\r",
+            output: Vec::new(),
+            ends: true,
+        };
+        let mut port = Rfc2217::new(connection).unwrap();
+        port.set_baud_rate(9600).unwrap();
+        port.set_parity(Parity::Even).unwrap();
+        assert_eq!(port.baud_rate(), None);
+        port.write_all(b"~\xff\r").unwrap();
+
+        let mut data = Vec::new();
+        let mut buf = [0u8; 8];
+        loop {
+            let len = port.read(&mut buf).unwrap();
+            if len == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..len]);
+        }
+        assert_eq!(data, b"~20\xff\r");
+        assert_eq!(port.baud_rate(), Some(9600));
+
+        let connection = port.into_inner();
+        assert_eq!(
+            connection.output,
+            [
+                // Offers of `new`
+                &b"\xff\xfb\x2c\xff\xfb\x00\xff\xfd\x00"[..],
+                b"\xff\xfa\x2c\x01\x00\x00\x25\x80\xff\xf0",
+                b"\xff\xfa\x2c\x03\x03\xff\xf0",
+                b"~\xff\xff\r",
+                // Replies to the offers of the server
+                b"\xff\xfd\x03\xff\xfe\x01",
+            ]
+            .concat()
+        );
+    }
+    #[test]
+    fn drain_notification() {
+        // NOTIFY-MODEMSTATE followed by data
+        const INPUT: &[u8] = b"\xff\xfa\x2c\x6b\x30\xff\xf0~20";
+        let connection = Connection {
+            input: &INPUT[..7],
+            output: Vec::new(),
+            ends: false,
+        };
+        let mut bms: PylontechBms<_> = PylontechBms::new(Rfc2217::new(connection).unwrap());
+        assert_eq!(bms.drain().unwrap(), 0);
+
+        let connection = Connection {
+            input: INPUT,
+            output: Vec::new(),
+            ends: false,
+        };
+        let mut bms: PylontechBms<_> = PylontechBms::new(Rfc2217::new(connection).unwrap());
+        assert_eq!(bms.drain().unwrap(), 3);
+    }
+}
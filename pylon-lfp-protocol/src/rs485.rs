@@ -0,0 +1,216 @@
+//! RS485 transceiver direction control
+//!
+//! Half-duplex transceivers have to be switched to transmitting (`DE`, driver enable)
+//! for the request and back to receiving (`/RE`, receiver enable) for the response.
+//! [DriverEnable] drives a GPIO wired to `DE` and `/RE` around the writes of a transport:
+//!
+//! ```rust
+//! # fn configure<U, P>(uart: U, de_pin: P)
+//! # where
+//! #     U: embedded_io::Read + embedded_io::Write,
+//! #     P: embedded_hal::digital::OutputPin,
+//! # {
+//! use embedded_hal::digital::PinState;
+//! use pylon_lfp_protocol::{PylontechBms, rs485::DriverEnable};
+//!
+//! let transport = DriverEnable::new(uart, de_pin, PinState::High).expect("Failed to release DE");
+//! let mut bms: PylontechBms<_> = PylontechBms::new(transport);
+//! # }
+//! ```
+
+use core::fmt::Display;
+
+use embedded_hal::digital::{OutputPin, PinState};
+use embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write};
+
+/// Error of a [DriverEnable] transport
+#[derive(Debug)]
+pub enum DriverEnableError<T, P> {
+    /// Error of the wrapped transport
+    Transport(T),
+    /// Error driving the pin
+    Pin(P),
+}
+impl<T: Display, P: core::fmt::Debug> Display for DriverEnableError<T, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DriverEnableError::Transport(e) => write!(f, "{e}"),
+            DriverEnableError::Pin(e) => write!(f, "Failed to drive the DE pin: {e:?}"),
+        }
+    }
+}
+impl<T: embedded_io::Error, P: embedded_hal::digital::Error> core::error::Error
+    for DriverEnableError<T, P>
+{
+}
+impl<T: embedded_io::Error, P: embedded_hal::digital::Error> embedded_io::Error
+    for DriverEnableError<T, P>
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            DriverEnableError::Transport(e) => e.kind(),
+            DriverEnableError::Pin(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// Transport wrapper driving the direction pin of an RS485 transceiver
+///
+/// The pin is asserted before the first byte is written and released once [Write::flush]
+/// returned, which has to wait until the last byte left the UART.
+/// Reading releases the pin as well, after flushing pending output.
+pub struct DriverEnable<U, P> {
+    uart: U,
+    pin: P,
+    /// Pin state enabling the driver
+    active: PinState,
+    transmitting: bool,
+}
+
+impl<U: ErrorType, P: OutputPin> DriverEnable<U, P> {
+    /// Wrap `uart`, `active` is the state of `pin` enabling the driver
+    ///
+    /// Releases the pin.
+    pub fn new(uart: U, mut pin: P, active: PinState) -> Result<Self, P::Error> {
+        pin.set_state(!active)?;
+        Ok(DriverEnable {
+            uart,
+            pin,
+            active,
+            transmitting: false,
+        })
+    }
+    /// Return the transport and the pin
+    pub fn into_inner(self) -> (U, P) {
+        (self.uart, self.pin)
+    }
+
+    fn assert(&mut self) -> Result<(), DriverEnableError<U::Error, P::Error>> {
+        if !self.transmitting {
+            self.pin
+                .set_state(self.active)
+                .map_err(DriverEnableError::Pin)?;
+            self.transmitting = true;
+        }
+        Ok(())
+    }
+    fn release(&mut self) -> Result<(), DriverEnableError<U::Error, P::Error>> {
+        if self.transmitting {
+            self.pin
+                .set_state(!self.active)
+                .map_err(DriverEnableError::Pin)?;
+            self.transmitting = false;
+        }
+        Ok(())
+    }
+}
+
+impl<U: Write, P: OutputPin> DriverEnable<U, P> {
+    /// Release the pin after pending output left the UART
+    fn finish_transmission(&mut self) -> Result<(), DriverEnableError<U::Error, P::Error>> {
+        match self.transmitting {
+            true => self.flush(),
+            false => Ok(()),
+        }
+    }
+}
+
+impl<U: ErrorType, P: OutputPin> ErrorType for DriverEnable<U, P> {
+    type Error = DriverEnableError<U::Error, P::Error>;
+}
+
+impl<U: Read + Write, P: OutputPin> Read for DriverEnable<U, P> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.finish_transmission()?;
+        self.uart.read(buf).map_err(DriverEnableError::Transport)
+    }
+}
+
+impl<U: ReadReady + Write, P: OutputPin> ReadReady for DriverEnable<U, P> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        self.finish_transmission()?;
+        self.uart.read_ready().map_err(DriverEnableError::Transport)
+    }
+}
+
+impl<U: Write, P: OutputPin> Write for DriverEnable<U, P> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.assert()?;
+        self.uart.write(buf).map_err(|e| {
+            // Never keep driving the bus
+            let _ = self.release();
+            DriverEnableError::Transport(e)
+        })
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let flushed = self.uart.flush().map_err(DriverEnableError::Transport);
+        // Never keep driving the bus, even if flushing failed
+        self.release()?;
+        flushed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, convert::Infallible};
+
+    use embedded_hal::digital::{ErrorType, OutputPin, PinState};
+    use embedded_io::{Read, Write};
+
+    use super::DriverEnable;
+    use crate::{
+        PylontechBms,
+        commands::Request,
+        dialect::Pylontech,
+        testing::{EncodedFrame, Exchange, MockTransport},
+    };
+
+    /// Pin recording its states
+    struct RecordingPin<'l>(&'l RefCell<Vec<PinState>>);
+    impl ErrorType for RecordingPin<'_> {
+        type Error = Infallible;
+    }
+    impl OutputPin for RecordingPin<'_> {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().push(PinState::Low);
+            Ok(())
+        }
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.0.borrow_mut().push(PinState::High);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn drive_around_requests() {
+        let request = EncodedFrame::request::<Pylontech>(&Request::GetProtocolVersion);
+        let response = EncodedFrame::protocol_version::<Pylontech>();
+        let script = [
+            Exchange::new(&request, &response),
+            Exchange::new(&request, &response),
+        ];
+        let states = RefCell::new(Vec::new());
+        let transport = DriverEnable::new(
+            MockTransport::new(&script),
+            RecordingPin(&states),
+            PinState::High,
+        )
+        .unwrap();
+        let mut bms: PylontechBms<_> = PylontechBms::new(transport);
+        bms.get_protocol_version().unwrap();
+        assert_eq!(
+            *states.borrow(),
+            [PinState::Low, PinState::High, PinState::Low]
+        );
+
+        // Reading without flushing releases the pin as well
+        let mut transport = bms.into_inner();
+        transport.write_all(&request).unwrap();
+        let mut buf = [0u8; 32];
+        assert!(transport.read(&mut buf).unwrap() > 0);
+        assert_eq!(states.borrow().len(), 5);
+        assert_eq!(states.borrow().last(), Some(&PinState::Low));
+        let (uart, _) = transport.into_inner();
+        assert_eq!(uart.completed(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+//! Discovery of the pack addresses responding on a bus
+//!
+//! Commissioning a stack often starts without knowing the address switch settings of the packs.
+//! [PylontechBms::scan] probes a range of addresses and reports which of them respond:
+//!
+//! ```rust,no_run
+//! # fn scan<U: embedded_io::Read + embedded_io::Write>(bms: &mut pylon_lfp_protocol::PylontechBms<U>) {
+//! use pylon_lfp_protocol::scan::Probe;
+//!
+//! for result in bms.scan(1..=16).analog_value(true) {
+//!     let result = result.expect("Transport failed");
+//!     if let Probe::Response(version) = result.protocol_version {
+//!         println!("Pack at {} speaks {version}", result.address);
+//!     }
+//! }
+//! # }
+//! ```
+
+use core::ops::RangeInclusive;
+
+use embedded_io::{ErrorKind, Read, ReadReady, Write};
+
+use crate::{
+    CommandCode, DrainFn, Error, MAX_UNENCODED_PAYLOAD_LEN, PylontechBms, ResponseCode, Version,
+    Wait,
+    dialect::{Dialect, ResponsePayload},
+    drain,
+};
+
+/// Outcome of probing an address with a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe<T = ()> {
+    /// Normal response
+    Response(T),
+    /// Error signaled by the BMS (e.g. [ResponseCode::AdrErr])
+    Error(ResponseCode),
+    /// No response within the timeout of the transport or the [Timing](crate::timing::Timing)
+    NoResponse,
+    /// A response that couldn't be decoded (e.g. a collision on the bus)
+    /// or from another address (e.g. a late response to the previous probe)
+    Invalid,
+}
+
+impl<T> Probe<T> {
+    /// Whether anything answered
+    pub fn responded(&self) -> bool {
+        !matches!(self, Probe::NoResponse)
+    }
+    /// Map the payload of a normal response
+    pub fn map<V>(self, f: impl FnOnce(T) -> Probe<V>) -> Probe<V> {
+        match self {
+            Probe::Response(response) => f(response),
+            Probe::Error(code) => Probe::Error(code),
+            Probe::NoResponse => Probe::NoResponse,
+            Probe::Invalid => Probe::Invalid,
+        }
+    }
+}
+
+/// Result of probing an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanResult {
+    /// Probed `ADR`
+    pub address: u8,
+    /// Response to "_get protocol version_"
+    pub protocol_version: Probe<Version>,
+    /// Response to "_get analog value_", `None` if not probed
+    pub analog_value: Option<Probe>,
+}
+
+impl ScanResult {
+    /// Whether any probe was answered
+    pub fn responded(&self) -> bool {
+        self.protocol_version.responded() || self.analog_value.is_some_and(|p| p.responded())
+    }
+}
+
+/// Iterator probing a range of addresses, see [PylontechBms::scan]
+///
+/// Yields a [ScanResult] for every address, timeouts are reported as [Probe::NoResponse].
+/// Other transport errors are yielded as error, the scan can be continued afterwards.
+pub struct Scan<'b, U: Read + Write, D: Dialect, T: Wait> {
+    bms: &'b mut PylontechBms<U, D, T>,
+    addresses: RangeInclusive<u8>,
+    analog_value: bool,
+    /// Drains stale input before every probe, see [Scan::drain]
+    drain: Option<DrainFn<U>>,
+}
+
+impl<'b, U: Read + Write, D: Dialect, T: Wait> Scan<'b, U, D, T> {
+    pub(crate) fn new(bms: &'b mut PylontechBms<U, D, T>, addresses: RangeInclusive<u8>) -> Self {
+        Scan {
+            bms,
+            addresses,
+            analog_value: false,
+            drain: None,
+        }
+    }
+    /// Also probe every address with "_get analog value_"
+    pub fn analog_value(mut self, enabled: bool) -> Self {
+        self.analog_value = enabled;
+        self
+    }
+
+    /// Send `command` to `address`, returns the version and length of the `INFO` field
+    fn probe(
+        &mut self,
+        address: u8,
+        command: CommandCode,
+        info: &[u8],
+        buf: &mut [u8],
+    ) -> Result<Probe<(Version, usize)>, Error<U::Error>> {
+        if let Some(drain) = self.drain {
+            drain(&mut self.bms.uart)?;
+        }
+        match self.bms.request(address, command, info, buf) {
+            Ok(frame) if frame.adr != address => Ok(Probe::Invalid),
+            Ok(frame) => Ok(Probe::Response((frame.ver, frame.info.len()))),
+            Err(Error::Response(code)) => Ok(Probe::Error(code)),
+            Err(Error::Transport(e)) if embedded_io::Error::kind(&e) == ErrorKind::TimedOut => {
+                Ok(Probe::NoResponse)
+            }
+            Err(Error::Timeout) => Ok(Probe::NoResponse),
+            Err(Error::Transport(e)) => Err(Error::Transport(e)),
+            Err(_) => Ok(Probe::Invalid),
+        }
+    }
+}
+
+impl<U: Read + Write + ReadReady, D: Dialect, T: Wait> Scan<'_, U, D, T> {
+    /// Discard stale input (e.g. a late response to the previous probe) before every probe
+    pub fn drain(mut self, enabled: bool) -> Self {
+        self.drain = if enabled { Some(drain::<U>) } else { None };
+        self
+    }
+}
+
+impl<U: Read + Write, D: Dialect, T: Wait> Iterator for Scan<'_, U, D, T> {
+    type Item = Result<ScanResult, Error<U::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let address = self.addresses.next()?;
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let protocol_version =
+            match self.probe(address, CommandCode::GetProtocolVersion, &[], &mut buf) {
+                Ok(probe) => probe.map(|(version, _)| Probe::Response(version)),
+                Err(e) => return Some(Err(e)),
+            };
+        let analog_value =
+            if self.analog_value {
+                match self.probe(address, CommandCode::GetAnalogValue, &[address], &mut buf) {
+                    Ok(probe) => Some(probe.map(|(_, len)| {
+                        match D::AnalogValue::from_payload(&buf[..len]) {
+                            Ok(_) => Probe::Response(()),
+                            Err(_) => Probe::Invalid,
+                        }
+                    })),
+                    Err(e) => return Some(Err(e)),
+                }
+            } else {
+                None
+            };
+        Some(Ok(ScanResult {
+            address,
+            protocol_version,
+            analog_value,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Probe, ScanResult};
+    use crate::{
+        CommandCode, Frame, MAX_UNENCODED_PAYLOAD_LEN, PylontechBms, ResponseCode, Version,
+        dialect::{Dialect, Pylontech},
+        testing::{EncodedFrame, Exchange, MockTransport},
+    };
+
+    /// Analog value response of the specification example
+    const ANALOG_VALUE: &[u8] = b"~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\r";
+
+    /// Request of `command` to `adr`
+    fn request(adr: u8, command: CommandCode, info: &[u8]) -> EncodedFrame {
+        EncodedFrame::new(&Frame::new(Pylontech::VERSION, adr, command.into(), info))
+    }
+    /// Response of `adr` with `info`
+    fn response(adr: u8, info: &[u8]) -> EncodedFrame {
+        EncodedFrame::new(&Frame::new(
+            Version::new(3, 5),
+            adr,
+            ResponseCode::Normal.into(),
+            info,
+        ))
+    }
+
+    #[test]
+    fn scan_addresses() {
+        let version = |adr| request(adr, CommandCode::GetProtocolVersion, &[]);
+        let analog = |adr| request(adr, CommandCode::GetAnalogValue, &[adr]);
+        let respond_version = response(2, &[]);
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let analog_value =
+            Frame::decode(&mut { ANALOG_VALUE }, &mut info_buf).expect("Error decoding packet");
+        let respond_analog = response(2, analog_value.info);
+        let adr_err = EncodedFrame::error::<Pylontech>(3, ResponseCode::AdrErr);
+        let (version_1, analog_1) = (version(1), analog(1));
+        let (version_2, analog_2) = (version(2), analog(2));
+        let (version_3, analog_3) = (version(3), analog(3));
+        let script = [
+            Exchange::no_response(&version_1),
+            Exchange::no_response(&analog_1),
+            Exchange::new(&version_2, &respond_version),
+            Exchange::new(&analog_2, &respond_analog),
+            Exchange::new(&version_3, &adr_err),
+            Exchange::new(&analog_3, b"~garbage\r"),
+        ];
+        let mut bms: PylontechBms<_> = PylontechBms::new(MockTransport::new(&script));
+        let results: Vec<ScanResult> = bms
+            .scan(1..=3)
+            .analog_value(true)
+            .map(Result::unwrap)
+            .collect();
+        bms.into_inner().assert_done();
+
+        assert!(!results[0].responded());
+        assert_eq!(results[0].analog_value, Some(Probe::NoResponse));
+        assert_eq!(
+            results[1].protocol_version,
+            Probe::Response(Version::new(3, 5))
+        );
+        assert_eq!(results[1].analog_value, Some(Probe::Response(())));
+        assert!(results[2].responded());
+        assert_eq!(
+            results[2].protocol_version,
+            Probe::Error(ResponseCode::AdrErr)
+        );
+        assert_eq!(results[2].analog_value, Some(Probe::Invalid));
+    }
+    #[test]
+    fn late_response() {
+        let version_1 = request(1, CommandCode::GetProtocolVersion, &[]);
+        let version_2 = request(2, CommandCode::GetProtocolVersion, &[]);
+        let late = response(1, &[]);
+        let script = [
+            Exchange::no_response(&version_1),
+            Exchange::new(&version_2, &late),
+        ];
+        let mut bms: PylontechBms<_> = PylontechBms::new(MockTransport::new(&script));
+        let results: Vec<ScanResult> = bms.scan(1..=2).drain(true).map(Result::unwrap).collect();
+        bms.into_inner().assert_done();
+
+        assert_eq!(results[0].protocol_version, Probe::NoResponse);
+        // The response of pack 1 doesn't make pack 2 appear
+        assert_eq!(results[1].protocol_version, Probe::Invalid);
+    }
+}
@@ -0,0 +1,113 @@
+use core::ops::Deref;
+
+use crate::{
+    commands::{AlarmInfo, AlarmInfoParseError},
+    dialect::ResponsePayload,
+};
+
+/// Response payload of a Seplos "_get alarm info_" command
+///
+/// Besides the per measurement [AlarmState](crate::types::AlarmState)s, Seplos boards append
+/// user-defined status bytes (alarm events, switch, balancing and system states).
+/// These are exposed through accessors returning [None] when the BMS didn't report them.
+#[derive(Debug)]
+pub struct SeplosAlarmInfo<'a>(AlarmInfo<'a>);
+
+impl<'a> SeplosAlarmInfo<'a> {
+    /// Offset of the "_on/off state_" in the user-defined status bytes
+    const ON_OFF_STATE: usize = 6;
+    /// Offset of the "_equalization state_" in the user-defined status bytes
+    const EQUALIZATION_STATE: usize = 7;
+    /// Offset of the "_system state_" in the user-defined status bytes
+    const SYSTEM_STATE: usize = 9;
+    /// Offset of the "_disconnection state_" in the user-defined status bytes
+    const DISCONNECTION_STATE: usize = 10;
+    /// Offset of "_alarm event 7_" in the user-defined status bytes
+    const ALARM_EVENT_7: usize = 12;
+
+    pub fn from_bytes(buf: &'a [u8]) -> Result<SeplosAlarmInfo<'a>, AlarmInfoParseError> {
+        AlarmInfo::from_bytes(buf).map(SeplosAlarmInfo)
+    }
+    /// Get "_alarm event_" `1` to `8`
+    ///
+    /// Every bit of an alarm event signals a separate alarm,
+    /// refer to the Seplos documentation for the meaning of each bit.
+    pub fn alarm_event(&self, event: u8) -> Option<u8> {
+        match event {
+            1..=6 => self.status_u8(event as usize - 1),
+            7..=8 => self.status_u8(Self::ALARM_EVENT_7 + event as usize - 7),
+            _ => None,
+        }
+    }
+    /// Switch states (discharge, charge, current limit, heating)
+    pub fn on_off_state(&self) -> Option<u8> {
+        self.status_u8(Self::ON_OFF_STATE)
+    }
+    /// Balancing state, one bit per cell
+    pub fn equalization_state(&self) -> Option<u16> {
+        self.status_u16(Self::EQUALIZATION_STATE)
+    }
+    /// System state (discharge, charge, floating charge, standby, shutdown)
+    pub fn system_state(&self) -> Option<u8> {
+        self.status_u8(Self::SYSTEM_STATE)
+    }
+    /// Disconnected cells, one bit per cell
+    pub fn disconnection_state(&self) -> Option<u16> {
+        self.status_u16(Self::DISCONNECTION_STATE)
+    }
+}
+impl<'a> Deref for SeplosAlarmInfo<'a> {
+    type Target = AlarmInfo<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a> ResponsePayload<'a> for SeplosAlarmInfo<'a> {
+    type Error = AlarmInfoParseError;
+
+    fn from_payload(buf: &'a [u8]) -> Result<Self, Self::Error> {
+        SeplosAlarmInfo::from_bytes(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeplosAlarmInfo;
+    use crate::{Frame, MAX_UNENCODED_PAYLOAD_LEN, types::AlarmState};
+
+    /// Alarm info response of a 16 cell Seplos pack
+    const PACKET: &[u8] = b"~20004A0060640001100000000000000000000000000000000106000000000002000000140100000000000300800800000000000000000000EAB5\r";
+
+    #[test]
+    fn parse_alarm_info() {
+        let mut packet = PACKET;
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+
+        let alarms = SeplosAlarmInfo::from_bytes(frame.info).expect("Failed to parse payload");
+        assert_eq!(alarms.pack_address, 1);
+        assert_eq!(alarms.cell_voltage_alarms.len(), 16);
+        assert!(
+            alarms.cell_voltage_alarms[..15]
+                .iter()
+                .all(|a| a.is_normal())
+        );
+        assert_eq!(
+            alarms.cell_voltage_alarms[15],
+            AlarmState::BELOW_LOWER_LIMIT
+        );
+        assert_eq!(alarms.temperature_alarms.len(), 6);
+        assert_eq!(alarms.temperature_alarms[5], AlarmState::ABOVE_UPPER_LIMIT);
+        assert!(alarms.charge_current_alarm.is_normal());
+        assert_eq!(alarms.status_bytes().len(), 20);
+        assert_eq!(alarms.alarm_event(1), Some(0x01));
+        assert_eq!(alarms.alarm_event(8), Some(0x00));
+        assert_eq!(alarms.alarm_event(9), None);
+        assert_eq!(alarms.on_off_state(), Some(0x03));
+        assert_eq!(alarms.equalization_state(), Some(0x0080));
+        assert_eq!(alarms.system_state(), Some(0x08));
+        assert_eq!(alarms.disconnection_state(), Some(0x0000));
+    }
+}
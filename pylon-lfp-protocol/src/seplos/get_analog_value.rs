@@ -0,0 +1,171 @@
+use zerocopy::{FromBytes, byteorder::big_endian};
+
+use crate::commands::AnalogValueParseError;
+use crate::dialect::ResponsePayload;
+use crate::types::{
+    Ampere, AmpereHours, ChangeFlags, Percent, Temperature, Volt,
+    exponents::{CENTI, DECI, MILLI},
+};
+
+/// Response payload of a Seplos "_get analog value_" command
+///
+/// Unlike the Pylontech layout a Seplos response always contains a single pack.
+/// Currents, total voltages and capacities are reported in 10 mA, 10 mV and 10 mAh.
+#[derive(Debug)]
+pub struct SeplosAnalogValue<'a> {
+    pub flags: ChangeFlags,
+    /// Address of the pack this response belongs to
+    pub pack_address: u8,
+    /// Cell voltages
+    pub cell_voltages: &'a [Volt<MILLI>],
+    /// Temperatures reported for this pack
+    ///
+    /// Seplos boards report four cell temperatures followed by
+    /// the ambient and the power (MOSFET) temperature.
+    pub temperatures: &'a [Temperature<DECI>],
+    /// Current total pack current
+    pub pack_current: Ampere<CENTI>,
+    /// Current total pack voltage
+    pub pack_voltage: Volt<CENTI>,
+    /// Current remaining charge
+    pub pack_remaining: AmpereHours<CENTI>,
+    /// Number of user-defined fields following
+    ///
+    /// Seplos specifies this to be `10`.
+    pub user_defined: u8,
+    /// Total (full charge) capacity of the pack
+    pub total_capacity: AmpereHours<CENTI>,
+    /// State of charge
+    pub state_of_charge: Percent<DECI>,
+    /// Rated (design) capacity of the pack
+    pub rated_capacity: AmpereHours<CENTI>,
+    /// Cycles of the pack
+    pub cell_cycles: u16,
+    /// State of health
+    pub state_of_health: Percent<DECI>,
+    /// Voltage at the pack terminals
+    pub port_voltage: Volt<CENTI>,
+}
+
+impl<'a> SeplosAnalogValue<'a> {
+    pub fn from_bytes(buf: &'a [u8]) -> Result<SeplosAnalogValue<'a>, AnalogValueParseError> {
+        let (flags, rest) =
+            ChangeFlags::read_from_prefix(buf).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (pack_address, rest) =
+            u8::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+
+        // Voltages
+        let (volt_count, rest) =
+            u8::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (cell_voltages, rest) =
+            <[Volt<MILLI>]>::ref_from_prefix_with_elems(rest, volt_count as usize)
+                .map_err(|_| AnalogValueParseError::InvalidInput)?;
+
+        // Temperatures
+        let (temp_count, rest) =
+            u8::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (temperatures, rest) =
+            <[Temperature<DECI>]>::ref_from_prefix_with_elems(rest, temp_count as usize)
+                .map_err(|_| AnalogValueParseError::InvalidInput)?;
+
+        let (pack_current, rest) =
+            Ampere::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (pack_voltage, rest) =
+            Volt::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (pack_remaining, rest) =
+            AmpereHours::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (user_defined, rest) =
+            u8::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (total_capacity, rest) =
+            AmpereHours::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (state_of_charge, rest) =
+            Percent::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (rated_capacity, rest) =
+            AmpereHours::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (cell_cycles, rest) = big_endian::U16::read_from_prefix(rest)
+            .map_err(|_| AnalogValueParseError::InvalidInput)?;
+        let (state_of_health, rest) =
+            Percent::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+        // Reserved fields following the port voltage are ignored
+        let (port_voltage, _reserved) =
+            Volt::read_from_prefix(rest).map_err(|_| AnalogValueParseError::InvalidInput)?;
+
+        Ok(SeplosAnalogValue {
+            flags,
+            pack_address,
+            cell_voltages,
+            temperatures,
+            pack_current,
+            pack_voltage,
+            pack_remaining,
+            user_defined,
+            total_capacity,
+            state_of_charge,
+            rated_capacity,
+            cell_cycles: cell_cycles.get(),
+            state_of_health,
+            port_voltage,
+        })
+    }
+}
+
+impl<'a> ResponsePayload<'a> for SeplosAnalogValue<'a> {
+    type Error = AnalogValueParseError;
+
+    fn from_payload(buf: &'a [u8]) -> Result<Self, Self::Error> {
+        SeplosAnalogValue::from_bytes(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeplosAnalogValue;
+    use crate::{Cid1, Frame, MAX_UNENCODED_PAYLOAD_LEN};
+
+    /// Analog value response of a 16 cell Seplos pack
+    const PACKET: &[u8] = b"~20004A0010960001100CE40CE50CE60CE70CE80CE90CEA0CEB0CEC0CED0CEE0CEF0CF00CF10CF20CF3060BA50BA60BA70BA80B9B0BC3FE0C14A03A980A4E2002EE4E20000603E8149F0000000000000000DC1A\r";
+
+    #[test]
+    fn decode_seplos_frame() {
+        let mut packet = PACKET;
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+        assert_eq!(frame.cid1, Cid1::SEPLOS_BATTERY_DATA);
+        assert_eq!(frame.adr, 0);
+        assert_eq!(frame.info.len(), 75);
+    }
+
+    #[test]
+    fn parse_analog_value() {
+        let mut packet = PACKET;
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+
+        let analog = SeplosAnalogValue::from_bytes(frame.info).expect("Failed to parse payload");
+        assert_eq!(analog.pack_address, 1);
+        assert_eq!(analog.cell_voltages.len(), 16);
+        assert_eq!(analog.cell_voltages[0].get_raw(), 3300);
+        assert_eq!(analog.cell_voltages[15].get_raw(), 3315);
+        assert_eq!(analog.temperatures.len(), 6);
+        assert_eq!(analog.temperatures[5].get_raw(), 3011);
+        assert_eq!(analog.pack_current.get_ampere(), -5.0);
+        assert_eq!(analog.pack_voltage.get_raw(), 5280);
+        assert_eq!(analog.pack_remaining.get_raw(), 15000);
+        assert_eq!(analog.user_defined, 10);
+        assert_eq!(analog.total_capacity.get_raw(), 20000);
+        assert_eq!(analog.state_of_charge.get_percent(), 75.0);
+        assert_eq!(analog.rated_capacity.get_raw(), 20000);
+        assert_eq!(analog.cell_cycles, 6);
+        assert_eq!(analog.state_of_health.get_percent(), 100.0);
+        assert_eq!(analog.port_voltage.get_raw(), 5279);
+    }
+
+    #[test]
+    fn parse_truncated_analog_value() {
+        let mut packet = PACKET;
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let frame = Frame::decode(&mut packet, &mut info_buf).expect("Error decoding packet");
+
+        assert!(SeplosAnalogValue::from_bytes(&frame.info[..60]).is_err());
+    }
+}
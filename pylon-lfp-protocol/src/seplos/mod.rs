@@ -0,0 +1,59 @@
+//! Seplos BMS (v2) protocol dialect
+//!
+//! Seplos BMS v2 boards use the same frame format as the Pylontech protocol
+//! (`~`/CR framing, ASCII hex encoding, `LENGTH` and frame checksums),
+//! but a different `CID1` ([Cid1::SEPLOS_BATTERY_DATA]) and their own payload layouts
+//! for analog values and alarm info.
+
+mod get_alarm_info;
+mod get_analog_value;
+
+pub use get_alarm_info::*;
+pub use get_analog_value::*;
+
+use crate::{
+    Cid1, CommandCode, PylontechBms, Version,
+    dialect::Dialect,
+    types::{
+        ScalingProfile,
+        exponents::{CENTI, DECI, MILLI},
+    },
+};
+
+/// Protocol version spoken by Seplos BMS v2 boards
+const SEPLOS_PROTOCOL_VERSION_MAJOR: u8 = 2;
+/// Protocol minor version spoken by Seplos BMS v2 boards
+const SEPLOS_PROTOCOL_VERSION_MINOR: u8 = 0;
+
+/// Seplos BMS v2 dialect
+///
+/// Commands are addressed to the pack in `ADR` and the command info.
+pub struct Seplos;
+
+impl Dialect for Seplos {
+    const CID1: Cid1 = Cid1::SEPLOS_BATTERY_DATA;
+    const VERSION: Version =
+        Version::new(SEPLOS_PROTOCOL_VERSION_MAJOR, SEPLOS_PROTOCOL_VERSION_MINOR);
+    const SCALING: ScalingProfile = ScalingProfile {
+        cell_voltage: MILLI,
+        total_voltage: CENTI,
+        current: CENTI,
+        amp_hours: CENTI,
+        temperature: DECI,
+    };
+
+    type AnalogValue<'a> = SeplosAnalogValue<'a>;
+    type AlarmInfo<'a> = SeplosAlarmInfo<'a>;
+
+    fn supports(command: CommandCode) -> bool {
+        matches!(
+            command,
+            CommandCode::GetProtocolVersion
+                | CommandCode::GetAnalogValue
+                | CommandCode::GetAlarmInfo
+        )
+    }
+}
+
+/// Seplos BMS v2
+pub type SeplosBms<U> = PylontechBms<U, Seplos>;
@@ -0,0 +1,242 @@
+//! Passive bus sniffer
+//!
+//! Decodes the traffic between a master (e.g. an inverter) and the BMS from a tapped line
+//! without sending anything. Commands are paired with the following response,
+//! which is parsed according to the command.
+//!
+//! ```rust
+//! use pylon_lfp_protocol::{
+//!     DecodeOptions, MAX_UNENCODED_PAYLOAD_LEN,
+//!     sniffer::{Response, Sniffed, Sniffer},
+//! };
+//!
+//! let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+//! let mut sniffer: Sniffer<'_> = Sniffer::new(&mut info_buf, DecodeOptions::default());
+//!
+//! let mut line: &[u8] = b"~2801464F0000FD91\r~280146000000FDAB\r";
+//! let mut events = 0;
+//! while !line.is_empty() {
+//!     let (consumed, event) = sniffer.push(line);
+//!     line = &line[consumed..];
+//!     match event {
+//!         Some(Sniffed::Request { request, .. }) => println!("Request {request:?}"),
+//!         Some(Sniffed::Response { response: Response::ProtocolVersion(version), .. }) => {
+//!             assert_eq!(version.to_string(), "v2.8")
+//!         }
+//!         Some(_) => panic!("Unexpected event"),
+//!         None => continue,
+//!     }
+//!     events += 1;
+//! }
+//! assert_eq!(events, 2);
+//! ```
+
+use core::marker::PhantomData;
+
+use zerocopy::FromBytes;
+
+use crate::{
+    Cid2, CommandCode, DecodeOptions, Frame, FrameDecoder, FrameError, ResponseCode, Version,
+    commands::{Request, SystemParameter},
+    dialect::{Dialect, Pylontech, ResponsePayload},
+};
+
+/// A frame observed by a [Sniffer]
+#[non_exhaustive]
+pub enum Sniffed<'a, D: Dialect = Pylontech> {
+    /// A command sent by the master
+    Request {
+        /// `ADR` of the frame
+        adr: u8,
+        request: Request<'a>,
+        /// The previous command, if it wasn't answered
+        unanswered: Option<CommandCode>,
+    },
+    /// A response of the BMS
+    Response {
+        /// `ADR` of the frame
+        adr: u8,
+        /// The command answered, `None` if it was missed
+        command: Option<CommandCode>,
+        response: Response<'a, D>,
+    },
+    /// A frame that couldn't be decoded or doesn't belong to the [Dialect]
+    Invalid(FrameError),
+}
+
+/// Payload of a response, parsed according to the command answered
+#[non_exhaustive]
+pub enum Response<'a, D: Dialect = Pylontech> {
+    /// Response to "_get protocol version_"
+    ProtocolVersion(Version),
+    /// Response to "_get system parameter_"
+    SystemParameter(&'a SystemParameter),
+    /// Response to "_get analog value_"
+    AnalogValue(D::AnalogValue<'a>),
+    /// Response to "_get alarm info_"
+    AlarmInfo(D::AlarmInfo<'a>),
+    /// `INFO` of a response to another or a missed command, or of a payload failing to parse
+    Other(&'a [u8]),
+    /// Error signaled by the BMS
+    Error(ResponseCode),
+}
+
+/// Push-based decoder pairing the commands and responses on a bus
+///
+/// Generic over the [Dialect] spoken on the bus, defaults to the [Pylontech] specification.
+pub struct Sniffer<'b, D: Dialect = Pylontech> {
+    decoder: FrameDecoder<'b>,
+    /// Command waiting for a response
+    pending: Option<CommandCode>,
+    dialect: PhantomData<D>,
+}
+
+impl<'b, D: Dialect> Sniffer<'b, D> {
+    /// Create a sniffer storing the `INFO` of frames in `buf`
+    pub fn new(buf: &'b mut [u8], options: DecodeOptions) -> Self {
+        Sniffer {
+            decoder: FrameDecoder::for_bus(buf, options),
+            pending: None,
+            dialect: PhantomData,
+        }
+    }
+    /// Number of bytes discarded before the current (or last) frame
+    ///
+    /// See [FrameDecoder::skipped].
+    pub fn skipped(&self) -> usize {
+        self.decoder.skipped()
+    }
+    /// Feed received bytes into the sniffer
+    ///
+    /// Consumes bytes until a frame is complete, remaining bytes have to be pushed again.
+    /// Returns the number of bytes consumed and the observed frame, if any.
+    pub fn push(&mut self, data: &[u8]) -> (usize, Option<Sniffed<'_, D>>) {
+        let (consumed, frame) = self.decoder.push(data);
+        let cid2 = match frame {
+            None => return (consumed, None),
+            Some(Ok(frame)) if frame.cid1 != D::CID1 => {
+                return (
+                    consumed,
+                    Some(Sniffed::Invalid(FrameError::UnsupportedControlIdentifier)),
+                );
+            }
+            Some(Ok(frame)) => frame.cid2,
+            Some(Err(FrameError::Response(code))) => Cid2::Response(code),
+            Some(Err(e)) => return (consumed, Some(Sniffed::Invalid(e))),
+        };
+
+        // A response follows a command and vice versa
+        let previous = match cid2 {
+            Cid2::Command(command) => self.pending.replace(command),
+            Cid2::Response(_) => self.pending.take(),
+        };
+        self.decoder
+            .prefer_command(matches!(cid2, Cid2::Response(_)));
+
+        let Some(frame) = self.decoder.frame_unchecked() else {
+            return (consumed, None);
+        };
+        let event = match frame.cid2 {
+            Cid2::Command(_) => match Request::from_frame(&frame) {
+                Ok(request) => Sniffed::Request {
+                    adr: frame.adr,
+                    request,
+                    unanswered: previous,
+                },
+                Err(_) => Sniffed::Invalid(FrameError::InvalidInput),
+            },
+            Cid2::Response(code) => Sniffed::Response {
+                adr: frame.adr,
+                command: previous,
+                response: Response::parse(previous, code, &frame),
+            },
+        };
+        (consumed, Some(event))
+    }
+}
+
+impl<'a, D: Dialect> Response<'a, D> {
+    /// Parse the response to `command`
+    fn parse(command: Option<CommandCode>, code: ResponseCode, frame: &Frame<'a>) -> Self {
+        if code != ResponseCode::Normal {
+            return Response::Error(code);
+        }
+        let info = frame.info;
+        let response = match command {
+            Some(CommandCode::GetProtocolVersion) => Some(Response::ProtocolVersion(frame.ver)),
+            Some(CommandCode::GetSystemParameter) => SystemParameter::ref_from_bytes(info)
+                .ok()
+                .map(Response::SystemParameter),
+            Some(CommandCode::GetAnalogValue) => D::AnalogValue::from_payload(info)
+                .ok()
+                .map(Response::AnalogValue),
+            Some(CommandCode::GetAlarmInfo) => D::AlarmInfo::from_payload(info)
+                .ok()
+                .map(Response::AlarmInfo),
+            _ => None,
+        };
+        response.unwrap_or(Response::Other(info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Response, Sniffed, Sniffer};
+    use crate::{DecodeOptions, MAX_UNENCODED_PAYLOAD_LEN};
+
+    #[test]
+    fn pair_requests_and_responses() {
+        let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut sniffer: Sniffer<'_> = Sniffer::new(
+            &mut info_buf,
+            DecodeOptions {
+                resync: true,
+                ..Default::default()
+            },
+        );
+        // Analog values, "get quantity of pack" answered by an address error (same CID2 `0x90`)
+        // after noise, an unanswered "get quantity of pack" and another request
+        let mut line: &[u8] = b"~28014642E00201FD2D\r~20014600C06E11010F0D450D440D450D440D450D440D3E0D450D4A0D4A0D4B0D4A0D4A0D4A0D4A050BC30BC30BC30BCD0BCD0000C725BF6802C3500002E553\r\
+            ~280146900000FDA2\r\x00\x7f~280246900000FDA1\r~280146900000FDA2\r~28014642E00201FD2D\r";
+        let mut events = Vec::new();
+        while !line.is_empty() {
+            let (consumed, event) = sniffer.push(line);
+            line = &line[consumed..];
+            match event {
+                Some(Sniffed::Request {
+                    request,
+                    unanswered,
+                    ..
+                }) => events.push(format!("{:?} {unanswered:?}", request.command())),
+                Some(Sniffed::Response {
+                    adr,
+                    command,
+                    response,
+                }) => {
+                    let response = match response {
+                        Response::AnalogValue(values) => {
+                            assert_eq!(values.get_pack_count(), 1);
+                            "AnalogValue".into()
+                        }
+                        Response::Error(code) => format!("{code:?}"),
+                        _ => "Other".into(),
+                    };
+                    events.push(format!("{adr} {command:?} {response}"));
+                }
+                Some(Sniffed::Invalid(e)) => panic!("Invalid frame {e:?}"),
+                None => {}
+            }
+        }
+        assert_eq!(
+            events,
+            [
+                "GetAnalogValue None",
+                "1 Some(GetAnalogValue) AnalogValue",
+                "GetQuantityOfPack None",
+                "2 Some(GetQuantityOfPack) AdrErr",
+                "GetQuantityOfPack None",
+                "GetAnalogValue Some(GetQuantityOfPack)",
+            ]
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! Scripted mock transport for testing code built on [PylontechBms](crate::PylontechBms)
+//!
+//! [MockTransport] plays a script of [Exchange]s: it asserts that every request written
+//! matches the expected frame (panicking otherwise) and replays the canned response.
+//! [EncodedFrame] builds requests and responses from typed data.
+//! [RecordingDelay] records the waiting of a [PylontechBms](crate::PylontechBms).
+//!
+//! ```rust
+//! use pylon_lfp_protocol::{
+//!     PylontechBms,
+//!     commands::{PackData, Request},
+//!     dialect::Pylontech,
+//!     testing::{EncodedFrame, Exchange, MockTransport},
+//!     types::{Ampere, AmpereHours, ChangeFlags, Temperature, Volt},
+//! };
+//!
+//! let cells = [Volt::new(3300); 15];
+//! let temperatures = [Temperature::new(2981); 5];
+//! let pack = PackData::new(
+//!     &cells,
+//!     &temperatures,
+//!     Ampere::new(-10000),
+//!     Volt::new(49500),
+//!     AmpereHours::new(40000),
+//!     AmpereHours::new(50000),
+//!     12,
+//! );
+//! let request = EncodedFrame::request::<Pylontech>(&Request::GetAnalogValue { pack_address: 1 });
+//! let response = EncodedFrame::analog_value::<Pylontech>(1, ChangeFlags::new(false, false), &[pack]);
+//! let script = [Exchange::new(&request, &response)];
+//!
+//! let mut bms: PylontechBms<_> = PylontechBms::new(MockTransport::new(&script));
+//! let mut buf = [0u8; pylon_lfp_protocol::MAX_UNENCODED_PAYLOAD_LEN];
+//! let measurements = bms.get_analog_value(1, &mut buf).unwrap();
+//! let parsed: PackData<'_> = measurements.get_pack(0).unwrap();
+//! assert_eq!(parsed.cell_cycles, 12);
+//! bms.into_inner().assert_done();
+//! ```
+
+use core::ops::Deref;
+
+use embedded_hal::delay::DelayNs;
+use embedded_io::{ErrorKind, ErrorType, Read, ReadReady, Write};
+use zerocopy::IntoBytes;
+
+use crate::{
+    Frame, MAX_UNENCODED_PAYLOAD_LEN, ResponseCode,
+    commands::{AnalogValueBuilder, PackData, Request, SystemParameter},
+    dialect::Dialect,
+    types::ChangeFlags,
+};
+
+/// Maximum length of an encoded frame (`SOI`, header, `INFO`, checksum and `EOI`)
+const MAX_FRAME_LEN: usize = 1 + 12 + 2 * MAX_UNENCODED_PAYLOAD_LEN + 5;
+/// Maximum number of delays a [RecordingDelay] records
+const MAX_DELAYS: usize = 32;
+
+/// A request expected by a [MockTransport] and the response to it
+#[derive(Debug, Clone, Copy)]
+pub struct Exchange<'a> {
+    /// Bytes expected to be written
+    pub request: &'a [u8],
+    /// Bytes read once the request was written
+    ///
+    /// Reads time out when empty or exhausted.
+    pub response: &'a [u8],
+}
+impl<'a> Exchange<'a> {
+    pub fn new(request: &'a [u8], response: &'a [u8]) -> Self {
+        Exchange { request, response }
+    }
+    /// A request not answered, reading times out
+    pub fn no_response(request: &'a [u8]) -> Self {
+        Exchange::new(request, &[])
+    }
+}
+
+/// Transport playing a script of [Exchange]s
+///
+/// Panics if a written byte doesn't match the expected request or the script is exhausted.
+/// Reads return the response of the current exchange once its request was written
+/// and fail with [ErrorKind::TimedOut] otherwise.
+/// Unread bytes of a response are dropped when the next request is written.
+#[derive(Debug)]
+pub struct MockTransport<'a> {
+    script: &'a [Exchange<'a>],
+    /// Index of the current exchange
+    index: usize,
+    /// Bytes of the current request written
+    written: usize,
+    /// Bytes of the current response read
+    read: usize,
+}
+
+impl<'a> MockTransport<'a> {
+    pub fn new(script: &'a [Exchange<'a>]) -> Self {
+        MockTransport {
+            script,
+            index: 0,
+            written: 0,
+            read: 0,
+        }
+    }
+    /// Number of exchanges whose request was completely written
+    pub fn completed(&self) -> usize {
+        match self.script.get(self.index) {
+            Some(exchange) if self.written == exchange.request.len() => self.index + 1,
+            _ => self.index,
+        }
+    }
+    /// Assert that every request of the script was written
+    pub fn assert_done(&self) {
+        assert_eq!(
+            self.completed(),
+            self.script.len(),
+            "Only {} of {} requests were written",
+            self.completed(),
+            self.script.len()
+        );
+    }
+    /// The part of the current response not read yet
+    fn pending_response(&self) -> &'a [u8] {
+        match self.script.get(self.index) {
+            Some(exchange) if self.written == exchange.request.len() => {
+                &exchange.response[self.read..]
+            }
+            _ => &[],
+        }
+    }
+}
+
+impl ErrorType for MockTransport<'_> {
+    type Error = ErrorKind;
+}
+impl Read for MockTransport<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let pending = self.pending_response();
+        if pending.is_empty() && !buf.is_empty() {
+            return Err(ErrorKind::TimedOut);
+        }
+        let len = pending.len().min(buf.len());
+        buf[..len].copy_from_slice(&pending[..len]);
+        self.read += len;
+        Ok(len)
+    }
+}
+impl ReadReady for MockTransport<'_> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.pending_response().is_empty())
+    }
+}
+impl Write for MockTransport<'_> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        for (i, &byte) in buf.iter().enumerate() {
+            // Start the next exchange once the current request is complete
+            while self
+                .script
+                .get(self.index)
+                .is_some_and(|exchange| self.written == exchange.request.len())
+            {
+                self.index += 1;
+                self.written = 0;
+                self.read = 0;
+            }
+            let Some(exchange) = self.script.get(self.index) else {
+                panic!("Unexpected request `{}`", buf[i..].escape_ascii());
+            };
+            assert_eq!(
+                byte,
+                exchange.request[self.written],
+                "Request {} differs at byte {}: expected `{}`, got `{}{}`",
+                self.index,
+                self.written,
+                exchange.request.escape_ascii(),
+                exchange.request[..self.written].escape_ascii(),
+                buf[i..].escape_ascii()
+            );
+            self.written += 1;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A frame encoded into a buffer, for building [Exchange]s from typed data
+///
+/// Requests and responses of a [Dialect] use its `VER`, `CID1` and `ADR`.
+pub struct EncodedFrame {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+}
+
+impl EncodedFrame {
+    /// Encode `frame`
+    ///
+    /// Panics if the `INFO` field is too large.
+    pub fn new(frame: &Frame<'_>) -> Self {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let mut out = buf.as_mut_slice();
+        frame.encode(&mut out).expect("Failed to encode frame");
+        let len = MAX_FRAME_LEN - out.len();
+        EncodedFrame { buf, len }
+    }
+    /// The request [PylontechBms](crate::PylontechBms) sends for `request`
+    pub fn request<D: Dialect>(request: &Request<'_>) -> Self {
+        let (pack_address, info) = match request {
+            Request::GetAnalogValue { pack_address }
+            | Request::GetAlarmInfo { pack_address }
+            | Request::GetCharge { pack_address }
+            | Request::GetSerialNumber { pack_address } => {
+                (*pack_address, core::slice::from_ref(pack_address))
+            }
+            Request::Other { info, .. } => (1, *info),
+            _ => (1, &[][..]),
+        };
+        Self::new(&Frame::new_with_cid1(
+            D::VERSION,
+            D::frame_address(pack_address),
+            D::CID1,
+            request.command().into(),
+            info,
+        ))
+    }
+    /// A normal response carrying the (unencoded) `info`
+    pub fn response<D: Dialect>(pack_address: u8, info: &[u8]) -> Self {
+        Self::new(&Frame::new_with_cid1(
+            D::VERSION,
+            D::frame_address(pack_address),
+            D::CID1,
+            ResponseCode::Normal.into(),
+            info,
+        ))
+    }
+    /// A response signaling the error `code`
+    pub fn error<D: Dialect>(pack_address: u8, code: ResponseCode) -> Self {
+        Self::new(&Frame::new_with_cid1(
+            D::VERSION,
+            D::frame_address(pack_address),
+            D::CID1,
+            code.into(),
+            &[],
+        ))
+    }
+    /// A "_get analog value_" response with `packs`
+    ///
+    /// Panics if the payload is too large.
+    pub fn analog_value<D: Dialect>(
+        pack_address: u8,
+        flags: ChangeFlags,
+        packs: &[PackData<'_>],
+    ) -> Self {
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let mut builder = AnalogValueBuilder::new(&mut buf, flags).expect("Buffer too small");
+        for pack in packs {
+            builder.add_pack(pack).expect("Failed to add pack");
+        }
+        Self::response::<D>(pack_address, builder.finish())
+    }
+    /// A "_get system parameter_" response
+    pub fn system_parameter<D: Dialect>(parameter: &SystemParameter) -> Self {
+        Self::response::<D>(1, parameter.as_bytes())
+    }
+    /// A "_get protocol version_" response
+    pub fn protocol_version<D: Dialect>() -> Self {
+        Self::response::<D>(1, &[])
+    }
+    /// The encoded bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+impl Deref for EncodedFrame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+impl core::fmt::Debug for EncodedFrame {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "EncodedFrame(\"{}\")", self.as_bytes().escape_ascii())
+    }
+}
+
+/// Delay recording the requested delays in µs instead of waiting
+///
+/// Panics if more than 32 delays are requested.
+#[derive(Debug, Default)]
+pub struct RecordingDelay {
+    delays: [u32; MAX_DELAYS],
+    len: usize,
+}
+
+impl RecordingDelay {
+    /// The delays requested so far in µs
+    pub fn delays(&self) -> &[u32] {
+        &self.delays[..self.len]
+    }
+}
+impl DelayNs for RecordingDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.delay_us(ns / 1000);
+    }
+    fn delay_us(&mut self, us: u32) {
+        assert!(self.len < MAX_DELAYS, "More than {MAX_DELAYS} delays");
+        self.delays[self.len] = us;
+        self.len += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EncodedFrame, Exchange, MockTransport};
+    use crate::{
+        Error, MAX_UNENCODED_PAYLOAD_LEN, PylontechBms, ResponseCode,
+        commands::{Request, SystemParameter},
+        dialect::Pylontech,
+        types::Volt,
+    };
+    use embedded_io::ErrorKind;
+    use zerocopy::FromZeros;
+
+    #[test]
+    fn scripted_exchanges() {
+        let version = EncodedFrame::request::<Pylontech>(&Request::GetProtocolVersion);
+        assert_eq!(version.as_bytes(), b"~2801464F0000FD91\r");
+        let version_response = EncodedFrame::protocol_version::<Pylontech>();
+        let analog =
+            EncodedFrame::request::<Pylontech>(&Request::GetAnalogValue { pack_address: 2 });
+        let error = EncodedFrame::error::<Pylontech>(2, ResponseCode::AdrErr);
+        let system = EncodedFrame::request::<Pylontech>(&Request::GetSystemParameter);
+        let mut parameter = SystemParameter::new_zeroed();
+        parameter.unit_cell_voltage = Volt::new(3300);
+        let parameter_response = EncodedFrame::system_parameter::<Pylontech>(&parameter);
+        let script = [
+            Exchange::new(&version, &version_response),
+            Exchange::new(&analog, &error),
+            Exchange::no_response(&system),
+            Exchange::new(&system, &parameter_response),
+        ];
+
+        let mut bms: PylontechBms<_> = PylontechBms::new(MockTransport::new(&script));
+        assert_eq!(bms.get_protocol_version().unwrap().to_string(), "v2.8");
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        assert!(matches!(
+            bms.get_analog_value(2, &mut buf),
+            Err(Error::Response(ResponseCode::AdrErr))
+        ));
+        assert!(matches!(
+            bms.get_system_parameter(),
+            Err(Error::Transport(ErrorKind::TimedOut))
+        ));
+        let parsed = bms.get_system_parameter().unwrap();
+        assert_eq!(parsed.unit_cell_voltage.get_raw(), 3300);
+        bms.into_inner().assert_done();
+    }
+    #[test]
+    #[should_panic(expected = "Request 0 differs at byte 14")]
+    fn unexpected_request() {
+        let request =
+            EncodedFrame::request::<Pylontech>(&Request::GetAnalogValue { pack_address: 1 });
+        let script = [Exchange::no_response(&request)];
+        let mut bms: PylontechBms<_> = PylontechBms::new(MockTransport::new(&script));
+        let mut buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+        let _ = bms.get_analog_value(2, &mut buf);
+    }
+}
@@ -0,0 +1,163 @@
+//! Timing of the request and response sequence on half-duplex lines
+//!
+//! On RS485 all devices share the line, the master has to leave it quiet between frames
+//! and give the transceivers time to turn around after transmitting.
+//! Embedded UARTs block until input arrives, [Timing] also bounds the time waited for a response.
+//!
+//! ```rust
+//! # fn configure<U: embedded_io::Read + embedded_io::Write + embedded_io::ReadReady>(
+//! #     uart: U,
+//! #     delay: impl embedded_hal::delay::DelayNs,
+//! # ) {
+//! use core::time::Duration;
+//! use pylon_lfp_protocol::{PylontechBms, timing::Timing};
+//!
+//! let bms: PylontechBms<_> = PylontechBms::new(uart);
+//! let mut bms = bms.with_delay(delay);
+//! bms.set_timing(Timing {
+//!     inter_frame_gap: Duration::from_millis(20),
+//!     turnaround: Duration::from_millis(1),
+//!     response_timeout: Some(Duration::from_millis(500)),
+//!     byte_timeout: Some(Duration::from_millis(10)),
+//! });
+//! # }
+//! ```
+
+use core::time::Duration;
+
+use embedded_io::{ErrorType, Read};
+
+use crate::Wait;
+
+/// Delays and timeouts of a request, waited using the delay of the [PylontechBms](crate::PylontechBms)
+///
+/// [Timing::NONE] is the default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// Quiet time on the line before every request
+    pub inter_frame_gap: Duration,
+    /// Delay after the request was transmitted before listening for the response
+    pub turnaround: Duration,
+    /// Maximum time until the first byte of the response, `None` waits for the transport
+    pub response_timeout: Option<Duration>,
+    /// Maximum silence between bytes of the response, `None` waits for the transport
+    pub byte_timeout: Option<Duration>,
+}
+
+impl Timing {
+    /// No delays, timeouts are left to the transport
+    pub const NONE: Timing = Timing {
+        inter_frame_gap: Duration::ZERO,
+        turnaround: Duration::ZERO,
+        response_timeout: None,
+        byte_timeout: None,
+    };
+    /// Interval input is polled at while a timeout is running
+    pub const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+    /// Whether input has to be polled for the timeouts
+    pub(crate) fn polls(&self) -> bool {
+        self.response_timeout.is_some() || self.byte_timeout.is_some()
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+/// Function checking whether input is ready, see [embedded_io::ReadReady]
+pub(crate) type ReadyFn<U> = fn(&mut U) -> Result<bool, <U as ErrorType>::Error>;
+
+/// Reader ending the input once a timeout of the [Timing] expires
+pub(crate) struct TimedRead<'u, U: Read, T: Wait> {
+    uart: &'u mut U,
+    delay: &'u mut T,
+    ready: ReadyFn<U>,
+    /// Timeout until the next byte
+    timeout: Option<Duration>,
+    byte_timeout: Option<Duration>,
+    /// Whether the input ended due to a timeout
+    pub timed_out: bool,
+}
+
+impl<'u, U: Read, T: Wait> TimedRead<'u, U, T> {
+    pub fn new(uart: &'u mut U, delay: &'u mut T, ready: ReadyFn<U>, timing: &Timing) -> Self {
+        TimedRead {
+            uart,
+            delay,
+            ready,
+            timeout: timing.response_timeout,
+            byte_timeout: timing.byte_timeout,
+            timed_out: false,
+        }
+    }
+}
+
+impl<U: Read, T: Wait> ErrorType for TimedRead<'_, U, T> {
+    type Error = U::Error;
+}
+
+impl<U: Read, T: Wait> Read for TimedRead<'_, U, T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if let Some(timeout) = self.timeout {
+            let mut waited = Duration::ZERO;
+            while !(self.ready)(self.uart)? {
+                if waited >= timeout {
+                    self.timed_out = true;
+                    return Ok(0);
+                }
+                self.delay.wait_us(Timing::POLL_INTERVAL.as_micros() as u32);
+                waited += Timing::POLL_INTERVAL;
+            }
+        }
+        self.timeout = self.byte_timeout;
+        self.uart.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::Timing;
+    use crate::{
+        Error, PylontechBms,
+        commands::Request,
+        dialect::Pylontech,
+        testing::{EncodedFrame, Exchange, MockTransport, RecordingDelay},
+    };
+
+    #[test]
+    fn delays_and_timeouts() {
+        let request = EncodedFrame::request::<Pylontech>(&Request::GetProtocolVersion);
+        let response = EncodedFrame::protocol_version::<Pylontech>();
+        let script = [
+            Exchange::new(&request, &response),
+            Exchange::no_response(&request),
+            Exchange::new(&request, b"~2801460"),
+        ];
+        let mut delay = RecordingDelay::default();
+        let bms: PylontechBms<_> = PylontechBms::new(MockTransport::new(&script));
+        let mut bms = bms.with_delay(&mut delay);
+        bms.set_timing(Timing {
+            inter_frame_gap: Duration::from_millis(2),
+            turnaround: Duration::from_micros(500),
+            response_timeout: Some(Duration::from_micros(300)),
+            byte_timeout: Some(Duration::from_micros(200)),
+        });
+        bms.get_protocol_version().unwrap();
+        assert!(matches!(bms.get_protocol_version(), Err(Error::Timeout)));
+        assert!(matches!(bms.get_protocol_version(), Err(Error::Timeout)));
+        bms.into_inner().assert_done();
+        assert_eq!(
+            delay.delays(),
+            [
+                2000, 500, // Response
+                2000, 500, 100, 100, 100, // Response timeout
+                2000, 500, 100, 100, // Byte timeout
+            ]
+        );
+    }
+}
@@ -38,6 +38,13 @@ pub mod exponents {
     pub const HECTO: i8 = 2;
     pub const KILO: i8 = 3;
     pub const MEGA: i8 = 6;
+    /// Whether `exp` is one of the supported metric prefixes (or `0`)
+    pub const fn is_supported(exp: i8) -> bool {
+        matches!(
+            exp,
+            MICRO | MILLI | CENTI | DECI | 0 | DECA | HECTO | KILO | MEGA
+        )
+    }
     pub(crate) const fn number(exp: i8) -> f32 {
         match exp {
             x if x == NANO => 0.000_000_001,
@@ -45,6 +52,7 @@ pub mod exponents {
             x if x == MILLI => 0.001,
             x if x == CENTI => 0.01,
             x if x == DECI => 0.1,
+            0 => 1.,
             x if x == DECA => 10.,
             x if x == HECTO => 100.,
             x if x == KILO => 1_000.,
@@ -54,6 +62,132 @@ pub mod exponents {
     }
 }
 
+/// Exponents of the binary representation of measurements
+///
+/// Describes which metric prefix each kind of measurement is transmitted in.
+/// See the [module documentation](self) for why this differs between battery packs.
+///
+/// This is the runtime counterpart to the _const generic_ exponents of the measurement types.
+/// It can be used to decode measurements into floating-point values in SI units
+/// when the representation is only known at runtime (e.g. loaded from a configuration),
+/// see [PackData::scaled](crate::commands::PackData::scaled) and
+/// [SystemParameter::scaled](crate::commands::SystemParameter::scaled).
+///
+/// When scaling a value the exponent of its type is ignored,
+/// only the raw value and the exponent of the profile are used.
+///
+/// Profiles are created with [ScalingProfile::new], which rejects unsupported exponents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScalingProfile {
+    /// Exponent of cell voltages
+    pub(crate) cell_voltage: i8,
+    /// Exponent of total (pack) voltages
+    pub(crate) total_voltage: i8,
+    /// Exponent of currents
+    pub(crate) current: i8,
+    /// Exponent of capacities
+    pub(crate) amp_hours: i8,
+    /// Exponent of temperatures
+    pub(crate) temperature: i8,
+}
+impl ScalingProfile {
+    /// Representation defined by the specification
+    pub const SPECIFICATION: ScalingProfile = ScalingProfile {
+        cell_voltage: MILLI,
+        total_voltage: MILLI,
+        current: MILLI,
+        amp_hours: MILLI,
+        temperature: DECI,
+    };
+    /// Representation used by "_Superpack_" branded packs
+    ///
+    /// Total voltage, current and capacities are transmitted in 10 mV, 10 mA and 10 mAh.
+    pub const SUPERPACK: ScalingProfile = ScalingProfile {
+        cell_voltage: MILLI,
+        total_voltage: CENTI,
+        current: CENTI,
+        amp_hours: CENTI,
+        temperature: DECI,
+    };
+
+    /// Create a new [ScalingProfile]
+    ///
+    /// Returns [None] if any exponent isn't supported (see [exponents::is_supported]).
+    pub const fn new(
+        cell_voltage: i8,
+        total_voltage: i8,
+        current: i8,
+        amp_hours: i8,
+        temperature: i8,
+    ) -> Option<Self> {
+        let profile = ScalingProfile {
+            cell_voltage,
+            total_voltage,
+            current,
+            amp_hours,
+            temperature,
+        };
+        if profile.is_valid() {
+            Some(profile)
+        } else {
+            None
+        }
+    }
+    /// Whether all exponents are supported
+    pub const fn is_valid(&self) -> bool {
+        is_supported(self.cell_voltage)
+            && is_supported(self.total_voltage)
+            && is_supported(self.current)
+            && is_supported(self.amp_hours)
+            && is_supported(self.temperature)
+    }
+    /// Exponent of cell voltages
+    pub const fn cell_voltage_exp(&self) -> i8 {
+        self.cell_voltage
+    }
+    /// Exponent of total (pack) voltages
+    pub const fn total_voltage_exp(&self) -> i8 {
+        self.total_voltage
+    }
+    /// Exponent of currents
+    pub const fn current_exp(&self) -> i8 {
+        self.current
+    }
+    /// Exponent of capacities
+    pub const fn amp_hours_exp(&self) -> i8 {
+        self.amp_hours
+    }
+    /// Exponent of temperatures
+    pub const fn temperature_exp(&self) -> i8 {
+        self.temperature
+    }
+    /// Cell voltage in Volt
+    pub fn cell_voltage<const EXP: i8>(&self, value: &Volt<EXP>) -> f32 {
+        value.get_raw() as f32 * number(self.cell_voltage)
+    }
+    /// Total voltage in Volt
+    pub fn total_voltage<const EXP: i8>(&self, value: &Volt<EXP>) -> f32 {
+        value.get_raw() as f32 * number(self.total_voltage)
+    }
+    /// Current in Ampere
+    pub fn current<const EXP: i8>(&self, value: &Ampere<EXP>) -> f32 {
+        value.get_raw() as f32 * number(self.current)
+    }
+    /// Charge in Ampere-hours
+    pub fn amp_hours<const EXP: i8>(&self, value: &AmpereHours<EXP>) -> f32 {
+        value.get_raw() as f32 * number(self.amp_hours)
+    }
+    /// Temperature in Kelvin
+    pub fn temperature<const EXP: i8>(&self, value: &Temperature<EXP>) -> f32 {
+        value.get_raw() as f32 * number(self.temperature)
+    }
+}
+impl Default for ScalingProfile {
+    fn default() -> Self {
+        Self::SPECIFICATION
+    }
+}
+
 /// Type alias for a voltage stored in Millivolt
 pub type MilliVolt = Volt<MILLI>;
 
@@ -61,7 +195,7 @@ pub type MilliVolt = Volt<MILLI>;
 ///
 /// Holds a scaled voltage in Volt.
 /// `EXP` is the metric prefix the voltage is stored in (e.g. a voltage stored in mV has a exponent of `-3`).
-#[derive(Debug, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
 #[repr(transparent)]
 pub struct Volt<const EXP: i8>(big_endian::U16);
 impl<const EXP: i8> Display for Volt<EXP> {
@@ -78,6 +212,10 @@ impl<const EXP: i8> Display for Volt<EXP> {
     }
 }
 impl<const EXP: i8> Volt<EXP> {
+    /// Create a voltage from the raw value
+    pub const fn new(raw: u16) -> Self {
+        Self(big_endian::U16::new(raw))
+    }
     /// Get the raw stored value
     ///
     /// The protocol specifies voltage as 16-bit.
@@ -97,7 +235,7 @@ impl<const EXP: i8> Volt<EXP> {
 pub type MilliAmpere = Ampere<MILLI>;
 
 /// Current
-#[derive(Debug, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
 #[repr(transparent)]
 pub struct Ampere<const EXP: i8>(big_endian::I16);
 impl<const EXP: i8> Display for Ampere<EXP> {
@@ -114,6 +252,10 @@ impl<const EXP: i8> Display for Ampere<EXP> {
     }
 }
 impl<const EXP: i8> Ampere<EXP> {
+    /// Create a current from the raw value
+    pub const fn new(raw: i16) -> Self {
+        Self(big_endian::I16::new(raw))
+    }
     /// Get the raw stored value
     pub fn get_raw(&self) -> i16 {
         self.0.get()
@@ -128,7 +270,7 @@ impl<const EXP: i8> Ampere<EXP> {
 pub type MilliAmpereHours = AmpereHours<MILLI>;
 
 /// Electric charge
-#[derive(Debug, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
 #[repr(transparent)]
 pub struct AmpereHours<const EXP: i8>(big_endian::U16);
 impl<const EXP: i8> Display for AmpereHours<EXP> {
@@ -145,6 +287,10 @@ impl<const EXP: i8> Display for AmpereHours<EXP> {
     }
 }
 impl<const EXP: i8> AmpereHours<EXP> {
+    /// Create a charge from the raw value
+    pub const fn new(raw: u16) -> Self {
+        Self(big_endian::U16::new(raw))
+    }
     /// Get the raw stored value
     pub fn get_raw(&self) -> u16 {
         self.0.get()
@@ -163,10 +309,14 @@ impl<const EXP: i8> AmpereHours<EXP> {
 /// The [Display] (`{}`) formatting displays the temperature in Kelvin with a precision of `1` by default.
 /// This can be changed by specifying the precision (e.g. `{:.2}`).
 /// Using the _alternate form_ (`{:#}`) displays the temperature in degree Celsius (`°C`).
-#[derive(Debug, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
 #[repr(transparent)]
 pub struct Temperature<const EXP: i8>(big_endian::U16);
 impl<const EXP: i8> Temperature<EXP> {
+    /// Create a temperature from the raw value
+    pub const fn new(raw: u16) -> Self {
+        Self(big_endian::U16::new(raw))
+    }
     /// The temperature in Kelvin
     pub fn kelvin(&self) -> f32 {
         self.0.get() as f32 * number(EXP)
@@ -200,13 +350,84 @@ impl<const EXP: i8> Display for Temperature<EXP> {
 /// Temperature representation defined by the specification
 pub type DeciKelvin = Temperature<DECI>;
 
+/// Relative value in percent
+///
+/// Used for state of charge (SoC) and state of health (SoH) in vendor dialects.
+/// `EXP` is the metric prefix of the stored value (e.g. a value stored in ‰ has an exponent of `-1`).
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
+#[repr(transparent)]
+pub struct Percent<const EXP: i8>(big_endian::U16);
+impl<const EXP: i8> Percent<EXP> {
+    /// Get the raw stored value
+    pub fn get_raw(&self) -> u16 {
+        self.0.get()
+    }
+    /// Floating-point value in percent
+    pub fn get_percent(&self) -> f32 {
+        self.get_raw() as f32 * number(EXP)
+    }
+}
+impl<const EXP: i8> Display for Percent<EXP> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if EXP == 0 {
+            write!(f, "{} %", self.0)
+        } else {
+            write!(f, "{:.1} %", self.get_percent())
+        }
+    }
+}
+
+/// Alarm state of a single measurement
+///
+/// Reported by "_get alarm info_" for cell voltages, temperatures, currents and the total voltage.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned,
+)]
+#[repr(transparent)]
+pub struct AlarmState(u8);
+impl AlarmState {
+    /// Value is within limits
+    pub const NORMAL: AlarmState = AlarmState(0x00);
+    /// Value is below lower limit
+    pub const BELOW_LOWER_LIMIT: AlarmState = AlarmState(0x01);
+    /// Value is above upper limit
+    pub const ABOVE_UPPER_LIMIT: AlarmState = AlarmState(0x02);
+    /// Other error
+    pub const OTHER: AlarmState = AlarmState(0xF0);
+
+    /// Get the raw stored value
+    pub fn get_raw(&self) -> u8 {
+        self.0
+    }
+    pub fn is_normal(&self) -> bool {
+        *self == Self::NORMAL
+    }
+}
+impl Display for AlarmState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::NORMAL => write!(f, "normal"),
+            Self::BELOW_LOWER_LIMIT => write!(f, "below lower limit"),
+            Self::ABOVE_UPPER_LIMIT => write!(f, "above upper limit"),
+            Self::OTHER => write!(f, "other error"),
+            Self(raw) => write!(f, "unknown ({raw:#04X})"),
+        }
+    }
+}
+
 /// Flags for switch and alarm change
 ///
 /// Referred to as `DATA_FLAG` in the specification.
-#[derive(Debug, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned,
+)]
 #[repr(transparent)]
 pub struct ChangeFlags(u8);
 impl ChangeFlags {
+    /// Create flags signaling unread switch and alarm changes
+    pub const fn new(switch_change: bool, alarm_change: bool) -> Self {
+        Self((switch_change as u8) << 4 | alarm_change as u8)
+    }
     pub fn switch_change(&self) -> bool {
         self.0 & 0b0001_0000 != 0
     }
@@ -260,6 +481,22 @@ mod tests {
         assert_eq!(temp.get_raw(), 3011);
     }
     #[test]
+    fn scaling_profile() {
+        let superpack = ScalingProfile::new(MILLI, CENTI, CENTI, CENTI, DECI);
+        assert_eq!(superpack, Some(ScalingProfile::SUPERPACK));
+        assert_eq!(ScalingProfile::new(MILLI, MILLI, 4, MILLI, DECI), None);
+
+        let superpack = superpack.unwrap();
+        assert_eq!(superpack.cell_voltage_exp(), MILLI);
+        assert_eq!(superpack.total_voltage_exp(), CENTI);
+        assert_eq!(superpack.current_exp(), CENTI);
+        assert_eq!(superpack.amp_hours_exp(), CENTI);
+        assert_eq!(superpack.temperature_exp(), DECI);
+
+        let current: Ampere<MILLI> = Ampere::new(1234);
+        assert_eq!(superpack.current(&current), 12.34);
+    }
+    #[test]
     fn format_volt() {
         use exponents::*;
         // Millivolt
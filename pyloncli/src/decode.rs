@@ -0,0 +1,422 @@
+//! `decode` subcommand dissecting frames from hex dumps and logs
+//!
+//! Frames are extracted from raw ASCII frames (`~2001...\r`, a literal `\r` is accepted),
+//! space separated hex bytes (`7E 32 30 ...`) and Rust byte string or array literals
+//! (`b"~2001...\r"`, `[0x7E, 0x32, ...]`).
+
+use std::{fmt::Display, io::Read, path::Path};
+
+use pylon_lfp_protocol::{
+    Cid1, CommandCode, Frame, InfoLength, ResponseCode, Version,
+    commands::{AlarmInfo, AnalogValueResponse, Request, SystemParameter},
+};
+use zerocopy::FromBytes;
+
+use crate::{Flavor, print_pack};
+
+/// Dissect all frames found in `input` (stdin if `None`)
+pub fn run(input: Option<&Path>, flavor: Option<Flavor>) {
+    let mut text = Vec::new();
+    let read = match input {
+        Some(path) => std::fs::read(path).map(|file| text = file),
+        None => std::io::stdin().read_to_end(&mut text).map(|_| ()),
+    };
+    if let Err(e) = read {
+        eprintln!("Failed to read input: {e}");
+        std::process::exit(1);
+    }
+    let bytes = input_bytes(&String::from_utf8_lossy(&text));
+    let frames = extract_frames(&bytes);
+    if frames.is_empty() {
+        eprintln!("No frames found");
+        std::process::exit(1);
+    }
+    let mut command = None;
+    for (n, frame) in frames.into_iter().enumerate() {
+        println!("Frame {}:", n + 1);
+        command = dissect(frame, command, flavor);
+        println!();
+    }
+}
+
+/// Convert the notations of the input to bytes
+///
+/// Lines of raw ASCII keep their line break, ending frames missing their `EOI`.
+fn input_bytes(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some(literal) = rest.strip_prefix("b\"") {
+            let end = literal_end(literal);
+            bytes.extend(unescape(&literal[..end]));
+            rest = literal.get(end + 1..).unwrap_or_default();
+        } else if let Some(array) = rest.strip_prefix('[') {
+            let end = array.find(']').unwrap_or(array.len());
+            bytes.extend(parse_array(&array[..end]));
+            rest = array.get(end + 1..).unwrap_or_default();
+        } else {
+            let end = ["\n", "b\"", "["]
+                .iter()
+                .filter_map(|delimiter| rest.find(delimiter))
+                .min()
+                .unwrap_or(rest.len());
+            let segment = &rest[..end];
+            if let Some(hex) = parse_hex_bytes(segment) {
+                bytes.extend(hex);
+            } else if let Some(start) = segment.find('~') {
+                bytes.extend(
+                    segment[start..]
+                        .trim_end_matches([' ', '\t', '\n'])
+                        .replace("\\r", "\r")
+                        .bytes(),
+                );
+                bytes.push(b'\n');
+            }
+            rest = rest[end..].strip_prefix('\n').unwrap_or(&rest[end..]);
+        }
+    }
+    bytes
+}
+
+/// Index of the `"` ending a string literal
+fn literal_end(literal: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in literal.char_indices() {
+        match c {
+            '"' if !escaped => return i,
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    literal.len()
+}
+
+/// Unescape the content of a byte string literal
+fn unescape(literal: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = literal.as_bytes();
+    while let [byte, tail @ ..] = rest {
+        rest = tail;
+        if *byte != b'\\' {
+            bytes.push(*byte);
+            continue;
+        }
+        let Some((escape, tail)) = rest.split_first() else {
+            break;
+        };
+        rest = tail;
+        match escape {
+            b'r' => bytes.push(b'\r'),
+            b'n' => bytes.push(b'\n'),
+            b't' => bytes.push(b'\t'),
+            b'0' => bytes.push(0),
+            b'x' => {
+                if let Some(byte) = rest.first_chunk::<2>().and_then(|hex| hex_u8(*hex)) {
+                    bytes.push(byte);
+                    rest = &rest[2..];
+                }
+            }
+            // Line continuation
+            b'\n' => rest = rest.trim_ascii_start(),
+            byte => bytes.push(*byte),
+        }
+    }
+    bytes
+}
+
+/// Parse the elements of an array literal, `None` if any isn't a byte
+fn parse_array(elements: &str) -> Vec<u8> {
+    let bytes: Option<Vec<u8>> = elements
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|element| !element.is_empty())
+        .map(|element| {
+            let element = element.trim_end_matches("u8").trim_end_matches('_');
+            match element.strip_prefix("0x").or(element.strip_prefix("0X")) {
+                Some(hex) => u8::from_str_radix(hex, 16).ok(),
+                None => element.parse().ok(),
+            }
+        })
+        .collect();
+    bytes.unwrap_or_default()
+}
+
+/// Parse a line of space separated hex bytes, skipping offsets (e.g. `0010:`)
+fn parse_hex_bytes(line: &str) -> Option<Vec<u8>> {
+    let bytes: Option<Vec<u8>> = line
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty() && !token.ends_with(':'))
+        .map(|token| {
+            let token = token.strip_prefix("0x").unwrap_or(token);
+            match token.len() {
+                2 => u8::from_str_radix(token, 16).ok(),
+                _ => None,
+            }
+        })
+        .collect();
+    bytes.filter(|bytes| !bytes.is_empty())
+}
+
+/// Split `bytes` into frames, starting at a `SOI`
+/// and ending after the `EOI` or before a line break or the next `SOI`
+fn extract_frames(bytes: &[u8]) -> Vec<&[u8]> {
+    let mut frames = Vec::new();
+    let mut rest = bytes;
+    while let Some(start) = rest.iter().position(|b| *b == b'~') {
+        rest = &rest[start..];
+        let end = match rest[1..]
+            .iter()
+            .position(|b| matches!(b, b'\r' | b'\n' | b'~'))
+        {
+            Some(end) if rest[end + 1] == b'\r' => end + 2,
+            Some(end) => end + 1,
+            None => rest.len(),
+        };
+        frames.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    frames
+}
+
+/// Print a field of the frame
+fn field(name: &str, raw: &[u8], meaning: impl Display) {
+    println!(
+        "  {name:<7} {:<8} {meaning}",
+        raw.escape_ascii().to_string()
+    );
+}
+
+/// Field of `N` ASCII characters at `pos`, prints a note if the frame is truncated
+fn ascii<const N: usize>(frame: &[u8], pos: usize, name: &str) -> Option<[u8; N]> {
+    let field = frame
+        .get(pos..pos + N)
+        .and_then(|field| field.try_into().ok());
+    if field.is_none() {
+        println!("  {name:<7} truncated");
+    }
+    field
+}
+
+fn hex_u8(ascii: [u8; 2]) -> Option<u8> {
+    u8::from_str_radix(std::str::from_utf8(&ascii).ok()?, 16).ok()
+}
+
+/// Print the fields of `frame`, returns the command if it is a command frame
+///
+/// `previous` is the command of the previous frame, a response to it is expected.
+fn dissect(
+    frame: &[u8],
+    previous: Option<CommandCode>,
+    flavor: Option<Flavor>,
+) -> Option<CommandCode> {
+    field("SOI", &frame[..1], "ok");
+
+    let ver = ascii::<2>(frame, 1, "VER")?;
+    match Version::decode_hex(&ver) {
+        Ok(version) => field("VER", &ver, version),
+        Err(_) => field("VER", &ver, "invalid hex"),
+    }
+    let adr = ascii::<2>(frame, 3, "ADR")?;
+    match hex_u8(adr) {
+        Some(address) => field("ADR", &adr, address),
+        None => field("ADR", &adr, "invalid hex"),
+    };
+    let cid1 = ascii::<2>(frame, 5, "CID1")?;
+    match Cid1::decode_hex(&cid1) {
+        Ok(Cid1::BATTERY_DATA) => field("CID1", &cid1, "battery data"),
+        Ok(Cid1::SEPLOS_BATTERY_DATA) => field("CID1", &cid1, "battery data (Seplos)"),
+        Ok(_) => field("CID1", &cid1, "vendor specific"),
+        Err(_) => field("CID1", &cid1, "invalid hex"),
+    }
+
+    // A response follows a command, codes valid as both (e.g. `0x90`) are resolved by that
+    let cid2 = ascii::<2>(frame, 7, "CID2")?;
+    let command = CommandCode::decode_hex(&cid2).ok();
+    let response = ResponseCode::decode_hex(&cid2).ok();
+    let (command, response) = match (command, response) {
+        (Some(_), Some(response)) if previous.is_some() => (None, Some(response)),
+        (Some(command), _) => (Some(command), None),
+        (None, response) => (None, response),
+    };
+    match (command, response) {
+        (Some(command), _) => field("CID2", &cid2, format_args!("command {command:?}")),
+        (_, Some(response)) => field("CID2", &cid2, format_args!("response {response:?}")),
+        _ => field("CID2", &cid2, "unknown"),
+    }
+
+    let length = ascii::<4>(frame, 9, "LENGTH")?;
+    let Ok(length) = InfoLength::decode_hex(&length).inspect_err(|_| {
+        field("LENGTH", &length, "invalid hex");
+    }) else {
+        return command;
+    };
+    let lchksum = if length.is_valid() {
+        "ok".to_string()
+    } else {
+        format!("expected {:X}", length.expected_lchksum())
+    };
+    field(
+        "LENGTH",
+        &frame[9..13],
+        format_args!(
+            "LENID {} ({} bytes), LCHKSUM {:X} {lchksum}",
+            length.length(),
+            length.length() / 2,
+            length.lchksum()
+        ),
+    );
+
+    let info_end = 13 + length.length() as usize;
+    let Some(info_ascii) = frame.get(13..info_end) else {
+        field("INFO", &frame[13..], "truncated");
+        return command;
+    };
+    let info: Option<Vec<u8>> = info_ascii
+        .chunks(2)
+        .map(|pair| hex_u8(pair.try_into().ok()?))
+        .collect();
+    match &info {
+        Some(info) => field("INFO", b"", format_args!("{} bytes", info.len())),
+        None => field("INFO", b"", "invalid hex"),
+    }
+
+    if let Some(chksum) = ascii::<4>(frame, info_end, "CHKSUM") {
+        let expected = Frame::checksum(&frame[1..info_end]);
+        let actual = std::str::from_utf8(&chksum)
+            .ok()
+            .and_then(|chksum| u16::from_str_radix(chksum, 16).ok());
+        match actual {
+            Some(actual) if actual == expected => field("CHKSUM", &chksum, "ok"),
+            Some(_) => field("CHKSUM", &chksum, format_args!("expected {expected:04X}")),
+            None => field(
+                "CHKSUM",
+                &chksum,
+                format_args!("invalid hex, expected {expected:04X}"),
+            ),
+        }
+        match &frame[info_end + 4..] {
+            [b'\r'] => field("EOI", b"\r", "ok"),
+            [] => println!("  EOI     missing"),
+            other => field("EOI", other, "expected \\r"),
+        }
+    }
+
+    if let Some(info) = info {
+        match (command, response) {
+            (Some(command), _) => match Request::from_info(command, &info) {
+                Ok(request) => println!("  {request:?}"),
+                Err(e) => println!("  Invalid command INFO: {e:?}"),
+            },
+            (_, Some(ResponseCode::Normal)) => {
+                dissect_response(&info, previous, Version::decode_hex(&ver).ok(), flavor)
+            }
+            _ => {}
+        }
+    }
+    command
+}
+
+/// Print the `INFO` of a response to `command`, guessed from the payload if unknown
+fn dissect_response(
+    info: &[u8],
+    command: Option<CommandCode>,
+    version: Option<Version>,
+    flavor: Option<Flavor>,
+) {
+    let command = command.or_else(|| {
+        let command = if info.is_empty() {
+            CommandCode::GetProtocolVersion
+        } else if info.len() == size_of::<SystemParameter>() {
+            CommandCode::GetSystemParameter
+        } else if AnalogValueResponse::from_bytes(info).is_ok() {
+            CommandCode::GetAnalogValue
+        } else {
+            return None;
+        };
+        println!("  Response to {command:?} (guessed)");
+        Some(command)
+    });
+    match command {
+        Some(CommandCode::GetProtocolVersion) => {
+            if let Some(version) = version {
+                println!("  Protocol version {version}");
+            }
+        }
+        Some(CommandCode::GetSystemParameter) => match SystemParameter::ref_from_bytes(info) {
+            Ok(parameter) => println!("{parameter}"),
+            Err(_) => println!("  Invalid system parameter"),
+        },
+        Some(CommandCode::GetAnalogValue) => match AnalogValueResponse::from_bytes(info) {
+            Ok(measurements) => {
+                let scaling = Flavor::scaling(flavor, &measurements);
+                println!(
+                    "  {} packs, {}",
+                    measurements.get_pack_count(),
+                    measurements.flags
+                );
+                for i in 0..measurements.get_pack_count() {
+                    println!("  --- Pack {i} ---");
+                    match measurements.get_pack_scaled(i, scaling) {
+                        Ok(pack) => print_pack(pack),
+                        Err(e) => println!("  Invalid pack data: {e:?}"),
+                    }
+                }
+            }
+            Err(e) => println!("  Invalid analog values: {e:?}"),
+        },
+        Some(CommandCode::GetAlarmInfo) => match AlarmInfo::from_bytes(info) {
+            Ok(alarms) => println!("  {alarms:?}"),
+            Err(e) => println!("  Invalid alarm info: {e:?}"),
+        },
+        _ => println!("  INFO {info:02X?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_frames, input_bytes};
+
+    /// "_get protocol version_" request of pack 1
+    const FRAME: &[u8] = b"~2801464F0000FD91\r";
+
+    /// Frames found in `text`
+    fn frames(text: &str) -> Vec<Vec<u8>> {
+        extract_frames(&input_bytes(text))
+            .into_iter()
+            .map(<[u8]>::to_vec)
+            .collect()
+    }
+
+    #[test]
+    fn raw_frame() {
+        assert_eq!(frames("TX: ~2801464F0000FD91\\r\n"), [FRAME]);
+        // A real carriage return is kept
+        assert_eq!(frames("~2801464F0000FD91\r\n"), [FRAME]);
+    }
+    #[test]
+    fn hex_dump() {
+        let dump = "0000: 7E 32 38 30 31 34 36 34\n\
+                    0008: 46 30 30 30 30 46 44 39\n\
+                    0010: 31 0D\n";
+        assert_eq!(frames(dump), [FRAME]);
+    }
+    #[test]
+    fn byte_string_literal() {
+        let literal = r#"let packet = b"~2801464F\
+                             0000FD91\r";"#;
+        assert_eq!(frames(literal), [FRAME]);
+    }
+    #[test]
+    fn array_literal() {
+        let mut array = String::from("const PACKET: [u8; 18] = [\n");
+        for byte in FRAME {
+            array.push_str(&format!("    0x{byte:02X},\n"));
+        }
+        array.push_str("];\n");
+        assert_eq!(frames(&array), [FRAME]);
+    }
+    #[test]
+    fn truncated_frame() {
+        let frames = frames("~2001460\n~2801464F0000FD91\\r\n");
+        assert_eq!(frames, [&b"~2001460"[..], FRAME]);
+    }
+}
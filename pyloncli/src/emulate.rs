@@ -0,0 +1,371 @@
+//! `emulate` subcommand serving a simulated battery stack
+//!
+//! The stack is configured by flags or a configuration file (`--config`) with one
+//! `key = value` pair per line. Keys are the long flag names, `#` starts a comment:
+//!
+//! ```text
+//! # Two 16 cell Superpacks discharging with 20 A
+//! flavor = superpack
+//! packs = 2
+//! cells = 16
+//! current = -20
+//! fault = bad-checksum
+//! fault-every = 10
+//! ```
+//!
+//! Flags override values of the configuration file.
+
+use std::{
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use clap::{Args, ValueEnum};
+use embedded_io_adapters::std::{FromStd, ToStd};
+use pylon_lfp_protocol::{
+    Error, ResponseCode,
+    dialect::{Dialect, Pylontech},
+    emulator::{Emulator, Fault, PackConfig, SimulatedPack},
+    types::ScalingProfile,
+};
+use serialport::{SerialPort, TTYPort};
+
+use crate::{
+    Device, Flavor, connected,
+    tcp::{self, Tcp},
+};
+
+/// Options of the `emulate` subcommand
+#[derive(Args, Clone, Default)]
+pub struct EmulateArgs {
+    /// Create a pseudo-terminal and link it to the device path instead of opening the device
+    #[arg(long)]
+    pty: bool,
+
+    /// Configuration file with `key = value` lines (keys as the long flags)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Number of packs in the stack [default: 1]
+    #[arg(long)]
+    packs: Option<u8>,
+
+    /// Cells per pack [default: 15]
+    #[arg(long)]
+    cells: Option<u8>,
+
+    /// Temperature sensors per pack [default: 5]
+    #[arg(long)]
+    temperatures: Option<u8>,
+
+    /// Capacity per pack in Ah [default: 50]
+    #[arg(long)]
+    capacity: Option<f32>,
+
+    /// Current per pack in A, positive while charging [default: -10]
+    #[arg(long, allow_negative_numbers = true)]
+    current: Option<f32>,
+
+    /// State of charge at start in percent [default: 80]
+    #[arg(long)]
+    soc: Option<f32>,
+
+    /// Cell voltage spread in mV [default: 10]
+    #[arg(long)]
+    spread: Option<f32>,
+
+    /// Fault to inject into responses
+    #[arg(long)]
+    fault: Option<FaultKind>,
+
+    /// Inject the fault into every n-th response [default: 1]
+    #[arg(long)]
+    fault_every: Option<u32>,
+
+    /// Battery pack type, the global `--flavor` flag
+    #[arg(skip)]
+    flavor: Option<Flavor>,
+}
+
+/// Faults to inject, see [Fault]
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FaultKind {
+    /// Don't respond
+    NoResponse,
+    /// Respond with a wrong checksum
+    BadChecksum,
+    /// Respond with a checksum error code
+    ChksumErr,
+    /// Respond with a CID2 error code
+    Cid2Err,
+    /// Respond with a address error code
+    AdrErr,
+    /// Respond with a internal communication error code
+    CommunicationErr,
+}
+impl From<FaultKind> for Fault {
+    fn from(kind: FaultKind) -> Self {
+        match kind {
+            FaultKind::NoResponse => Fault::NoResponse,
+            FaultKind::BadChecksum => Fault::BadChecksum,
+            FaultKind::ChksumErr => Fault::Response(ResponseCode::ChksumErr),
+            FaultKind::Cid2Err => Fault::Response(ResponseCode::Cid2Err),
+            FaultKind::AdrErr => Fault::Response(ResponseCode::AdrErr),
+            FaultKind::CommunicationErr => Fault::Response(ResponseCode::CommunicationErr),
+        }
+    }
+}
+
+impl EmulateArgs {
+    /// Fill options not given as flags from the configuration file
+    fn merge_config(self) -> Result<Self, String> {
+        let Some(path) = &self.config else {
+            return Ok(self);
+        };
+        let config = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        self.merge(&config)
+    }
+    /// Fill options not given as flags from the `key = value` lines of `config`
+    fn merge(mut self, config: &str) -> Result<Self, String> {
+        for (n, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| format!("Line {}: expected `key = value`", n + 1))?;
+            let invalid = |e: &dyn std::fmt::Display| format!("Line {}: {key}: {e}", n + 1);
+            match key {
+                "packs" => set(&mut self.packs, value.parse().map_err(|e| invalid(&e))?),
+                "cells" => set(&mut self.cells, value.parse().map_err(|e| invalid(&e))?),
+                "temperatures" => set(
+                    &mut self.temperatures,
+                    value.parse().map_err(|e| invalid(&e))?,
+                ),
+                "capacity" => set(&mut self.capacity, value.parse().map_err(|e| invalid(&e))?),
+                "current" => set(&mut self.current, value.parse().map_err(|e| invalid(&e))?),
+                "soc" => set(&mut self.soc, value.parse().map_err(|e| invalid(&e))?),
+                "spread" => set(&mut self.spread, value.parse().map_err(|e| invalid(&e))?),
+                "fault" => set(
+                    &mut self.fault,
+                    FaultKind::from_str(value, true).map_err(|e| invalid(&e))?,
+                ),
+                "fault-every" => set(
+                    &mut self.fault_every,
+                    value.parse().map_err(|e| invalid(&e))?,
+                ),
+                "flavor" => set(
+                    &mut self.flavor,
+                    Flavor::from_str(value, true).map_err(|e| invalid(&e))?,
+                ),
+                _ => return Err(format!("Line {}: unknown key `{key}`", n + 1)),
+            }
+        }
+        Ok(self)
+    }
+    /// Configuration of every pack
+    fn pack_config(&self) -> PackConfig {
+        let default = PackConfig::default();
+        PackConfig {
+            cells: self.cells.unwrap_or(default.cells),
+            temperatures: self.temperatures.unwrap_or(default.temperatures),
+            capacity: self.capacity.unwrap_or(default.capacity),
+            current: self.current.unwrap_or(default.current),
+            initial_soc: self.soc.map_or(default.initial_soc, |soc| soc / 100.),
+            cell_spread: self
+                .spread
+                .map_or(default.cell_spread, |spread| spread / 1000.),
+            ..default
+        }
+    }
+}
+
+/// Set `option` unless it was given as flag
+fn set<T>(option: &mut Option<T>, value: T) {
+    option.get_or_insert(value);
+}
+
+/// Serve a simulated stack on `device` until the transport fails
+pub fn run(
+    device: Device,
+    baud: u32,
+    timeout: Duration,
+    flavor: Option<Flavor>,
+    args: EmulateArgs,
+) {
+    let args = EmulateArgs { flavor, ..args }
+        .merge_config()
+        .unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(2);
+        });
+    let scaling = match args.flavor {
+        Some(Flavor::Superpack) => ScalingProfile::SUPERPACK,
+        Some(Flavor::Auto) => {
+            eprintln!("Warning: `--flavor auto` can't be emulated, using the Pylontech default");
+            Pylontech::SCALING
+        }
+        None => Pylontech::SCALING,
+    };
+
+    match device {
+        Device::Serial(path) => {
+            if let Err(e) = serve_serial(path, baud, timeout, scaling, &args) {
+                eprintln!("Failed to serve on {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+        Device::Tcp(address) => {
+            let tcp = connected(Tcp::connect(address, timeout));
+            serve(tcp, address, scaling, &args);
+        }
+        Device::TcpListen(address) => {
+            let listener = connected(Tcp::listen(address));
+            // Serve one converter after the other
+            loop {
+                let tcp = connected(Tcp::accept(&listener, timeout));
+                serve(tcp, address, scaling, &args);
+            }
+        }
+        Device::Rfc2217(address) => {
+            let port = connected(tcp::rfc2217(address, baud, timeout));
+            serve(ToStd::new(port), address, scaling, &args);
+        }
+    }
+}
+
+/// Serve on the serial port or pseudo-terminal at `device`
+fn serve_serial(
+    device: &Path,
+    baud: u32,
+    timeout: Duration,
+    scaling: ScalingProfile,
+    args: &EmulateArgs,
+) -> io::Result<()> {
+    if args.pty {
+        // Keep the slave open, the master fails reading once all slaves are closed
+        let (mut master, slave) = TTYPort::pair()?;
+        master.set_timeout(timeout)?;
+        let name = slave
+            .name()
+            .ok_or_else(|| io::Error::other("pseudo-terminal without name"))?;
+        // Replace a link left over from an earlier run
+        if device.is_symlink() {
+            std::fs::remove_file(device)?;
+        }
+        std::os::unix::fs::symlink(&name, device)?;
+        let name = format!("{name} linked to {}", device.display());
+        serve(master, &name, scaling, args);
+        std::fs::remove_file(device)
+    } else {
+        let port = serialport::new(device.to_string_lossy(), baud)
+            .timeout(timeout)
+            .open()?;
+        serve(port, &device.to_string_lossy(), scaling, args);
+        Ok(())
+    }
+}
+
+/// Answer requests on `port` named `name` until it fails
+fn serve<P: std::io::Read + std::io::Write>(
+    port: P,
+    name: &str,
+    scaling: ScalingProfile,
+    args: &EmulateArgs,
+) {
+    println!("Emulating on {name}");
+    let config = args.pack_config();
+    let mut packs: Vec<_> = (0..args.packs.unwrap_or(1))
+        .map(|i| SimulatedPack::new(config, i.into()))
+        .collect();
+    let mut emulator = Emulator::new(FromStd::new(port), &mut packs).scaling(scaling);
+    if let Some(fault) = args.fault {
+        emulator.inject_fault(Some((fault.into(), args.fault_every.unwrap_or(1))));
+    }
+
+    let mut last = Instant::now();
+    loop {
+        match emulator.serve() {
+            Ok(Some(command)) => println!("Served {command:?}"),
+            Ok(None) => {}
+            Err(Error::Transport(e)) if e.kind() == ErrorKind::TimedOut => {}
+            Err(Error::Transport(e)) => {
+                eprintln!("Transport error: {e}");
+                break;
+            }
+            Err(e) => eprintln!("Invalid request: {e}"),
+        }
+        let now = Instant::now();
+        emulator.advance(now - last);
+        last = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmulateArgs, FaultKind};
+    use crate::Flavor;
+
+    #[test]
+    fn merge_known_keys() {
+        let config = "# Stack\n\
+                      packs = 2\n\
+                      cells = 16 # per pack\n\
+                      temperatures=4\n\
+                      capacity = 100\n\
+                      current = -20.5\n\
+                      soc = 50\n\
+                      spread = 5\n\
+                      fault = bad-checksum\n\
+                      fault-every = 10\n\
+                      flavor = superpack\n";
+        let args = EmulateArgs::default().merge(config).unwrap();
+        assert_eq!(args.packs, Some(2));
+        assert_eq!(args.cells, Some(16));
+        assert_eq!(args.temperatures, Some(4));
+        assert_eq!(args.capacity, Some(100.));
+        assert_eq!(args.current, Some(-20.5));
+        assert_eq!(args.soc, Some(50.));
+        assert_eq!(args.spread, Some(5.));
+        assert!(args.fault == Some(FaultKind::BadChecksum));
+        assert_eq!(args.fault_every, Some(10));
+        assert!(args.flavor == Some(Flavor::Superpack));
+    }
+    #[test]
+    fn merge_unknown_key() {
+        let e = EmulateArgs::default().merge("packs = 2\nvoltage = 48\n");
+        assert_eq!(e.err().unwrap(), "Line 2: unknown key `voltage`");
+        let e = EmulateArgs::default().merge("packs 2\n");
+        assert_eq!(e.err().unwrap(), "Line 1: expected `key = value`");
+    }
+    #[test]
+    fn merge_invalid_value() {
+        let e = EmulateArgs::default().merge("packs = 300\n").err().unwrap();
+        assert!(e.starts_with("Line 1: packs: "), "{e}");
+        let e = EmulateArgs::default()
+            .merge("fault = fire\n")
+            .err()
+            .unwrap();
+        assert!(e.starts_with("Line 1: fault: "), "{e}");
+        let e = EmulateArgs::default().merge("flavor = x\n").err().unwrap();
+        assert!(e.starts_with("Line 1: flavor: "), "{e}");
+    }
+    #[test]
+    fn flags_override_config() {
+        let args = EmulateArgs {
+            packs: Some(3),
+            flavor: Some(Flavor::Auto),
+            ..EmulateArgs::default()
+        };
+        let args = args
+            .merge("packs = 2\ncells = 16\nflavor = superpack\n")
+            .unwrap();
+        assert_eq!(args.packs, Some(3));
+        assert_eq!(args.cells, Some(16));
+        assert!(args.flavor == Some(Flavor::Auto));
+    }
+}
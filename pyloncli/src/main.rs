@@ -1,17 +1,45 @@
-use std::{borrow::Cow, path::PathBuf, time::Duration};
+use std::{
+    borrow::Cow,
+    fmt::Display,
+    io::BufWriter,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use clap::{Parser, Subcommand, ValueEnum};
+use emulate::EmulateArgs;
+use tcp::Tcp;
 
-use embedded_io::{Read, Write};
-use embedded_io_adapters::std::FromStd;
-use pylon_lfp_protocol::{PylontechBms, commands::PackData, types::exponents::*};
+use embedded_hal::delay::DelayNs;
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use embedded_io_adapters::std::{FromStd, ToStd};
+use pylon_lfp_protocol::{
+    DecodeOptions, PylontechBms,
+    capture::{Recorder, Replay},
+    commands::{AnalogValueResponse, ScaledPackData},
+    detect::detect_scaling,
+    dialect::{Dialect, Pylontech},
+    retry::RetryPolicy,
+    scan::Probe,
+    types::ScalingProfile,
+};
+
+mod decode;
+mod emulate;
+mod sniff;
+mod tcp;
 
 /// A Command Line tool to interact with batteries implementing the Pylontech RS232 protocol
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Serial device to use
-    device: PathBuf,
+    /// Serial device to use, `tcp://host:port` to connect to a serial-to-Ethernet converter,
+    /// `tcp-listen://address:port` to wait for a converter connecting
+    /// or `rfc2217://host:port` to connect to a RFC 2217 serial server (with `--baud`)
+    /// (with `emulate --pty` the path of the link to create, with `--replay` the capture file),
+    /// required by all commands but `decode`
+    device: Option<PathBuf>,
 
     /// Battery pack address
     #[arg(short, long, default_value_t = 1)]
@@ -25,7 +53,27 @@ struct Args {
     #[arg(short, long, default_value_t = 1000)]
     timeout: u64,
 
-    /// Battery pack type (omit for specification default)
+    /// Skip line noise and stale input instead of failing
+    #[arg(short, long)]
+    resync: bool,
+
+    /// Retry requests failing on transient errors (timeouts, corrupted frames) up to N times
+    #[arg(long, default_value_t = 0)]
+    retries: u8,
+
+    /// Tolerate deviations of non-conforming BMS firmware (lowercase hex, bad checksums, missing EOI)
+    #[arg(short, long)]
+    lenient: bool,
+
+    /// Record all traffic of a request command to a capture file
+    #[arg(short, long)]
+    capture: Option<PathBuf>,
+
+    /// Replay a capture file given as device instead of opening a serial device (request commands only)
+    #[arg(long)]
+    replay: bool,
+
+    /// Battery pack type (omit for specification default, `auto` to detect)
     #[arg(short, long)]
     flavor: Option<Flavor>,
 
@@ -34,6 +82,18 @@ struct Args {
     command: Commands,
 }
 
+impl Args {
+    /// [DecodeOptions] selected by `--lenient`
+    fn decode_options(&self, resync: bool) -> DecodeOptions {
+        let options = if self.lenient {
+            DecodeOptions::LENIENT
+        } else {
+            DecodeOptions::STRICT
+        };
+        DecodeOptions { resync, ..options }
+    }
+}
+
 #[derive(Subcommand)]
 #[allow(clippy::enum_variant_names)]
 enum Commands {
@@ -47,44 +107,296 @@ enum Commands {
         #[arg(short, long)]
         pack_address: Option<u8>,
     },
+    /// Probe a range of addresses for responding packs
+    Scan {
+        /// First address to probe
+        #[arg(long, default_value_t = 1)]
+        first: u8,
+        /// Last address to probe
+        #[arg(long, default_value_t = 16)]
+        last: u8,
+        /// Also probe with "get analog value"
+        #[arg(long)]
+        analog_value: bool,
+    },
+    /// Answer requests as a simulated battery stack
+    Emulate(EmulateArgs),
+    /// Listen on a line polled by another master and print the decoded traffic
+    Sniff,
+    /// Dissect frames given as raw ASCII, hex bytes or Rust byte literals field by field
+    Decode {
+        /// File to read the frames from, stdin if not specified
+        input: Option<PathBuf>,
+    },
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Flavor {
     /// Superpack branded packs
     Superpack,
+    /// Detect the flavor from the measurements
+    Auto,
+}
+
+/// Confidence a detected flavor needs to be used or suggested
+const DETECTION_CONFIDENCE: f32 = 0.5;
+
+impl Flavor {
+    /// The [ScalingProfile] of a flavor, `None` selects the default of the [Pylontech] dialect
+    ///
+    /// Warns if the measurements suggest a different flavor than selected.
+    fn scaling(flavor: Option<Flavor>, measurements: &AnalogValueResponse<'_>) -> ScalingProfile {
+        let detection = detect_scaling(measurements, None)
+            .filter(|detection| detection.confidence >= DETECTION_CONFIDENCE);
+        match flavor {
+            Some(Flavor::Superpack) => ScalingProfile::SUPERPACK,
+            Some(Flavor::Auto) => match detection {
+                Some(detection) => detection.profile,
+                None => {
+                    eprintln!("Warning: could not detect the flavor, using the Pylontech default");
+                    Pylontech::SCALING
+                }
+            },
+            None => {
+                if let Some(detection) = detection
+                    && detection.profile != Pylontech::SCALING
+                {
+                    eprintln!(
+                        "Warning: measurements look like a different flavor, try `--flavor auto`"
+                    );
+                }
+                Pylontech::SCALING
+            }
+        }
+    }
+}
+
+/// Transport selected by the device argument
+#[derive(Clone, Copy)]
+enum Device<'a> {
+    /// Local serial port
+    Serial(&'a Path),
+    /// TCP server of a converter (`tcp://host:port`)
+    Tcp(&'a str),
+    /// Converter connecting as TCP client (`tcp-listen://address:port`)
+    TcpListen(&'a str),
+    /// RFC 2217 serial server (`rfc2217://host:port`)
+    Rfc2217(&'a str),
+}
+
+impl<'a> Device<'a> {
+    fn parse(device: &'a Path) -> Self {
+        let url = device.to_str().unwrap_or_default();
+        if let Some(address) = url.strip_prefix("tcp://") {
+            Device::Tcp(address)
+        } else if let Some(address) = url.strip_prefix("tcp-listen://") {
+            Device::TcpListen(address)
+        } else if let Some(address) = url.strip_prefix("rfc2217://") {
+            Device::Rfc2217(address)
+        } else {
+            Device::Serial(device)
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
+    let timeout = Duration::from_millis(args.timeout);
 
-    let port = serialport::new(Cow::from(args.device.to_str().unwrap()), args.baud)
-        .timeout(Duration::from_millis(args.timeout))
-        .open()
-        .unwrap();
+    // Only requests are replayed from and recorded to captures
+    if let Commands::Emulate(_) | Commands::Sniff | Commands::Decode { .. } = args.command {
+        if args.replay {
+            eprintln!("`--replay` can't be used with `emulate`, `sniff` or `decode`");
+            std::process::exit(2);
+        }
+        if args.capture.is_some() {
+            eprintln!("`--capture` can't be used with `emulate`, `sniff` or `decode`");
+            std::process::exit(2);
+        }
+    }
+    if let Commands::Decode { input } = &args.command {
+        decode::run(input.as_deref(), args.flavor);
+        return;
+    }
+    let Some(device) = &args.device else {
+        eprintln!("A device is required");
+        std::process::exit(2);
+    };
 
-    let device = FromStd::new(port);
+    if let Commands::Emulate(emulate) = &args.command {
+        emulate::run(
+            Device::parse(device),
+            args.baud,
+            timeout,
+            args.flavor,
+            emulate.clone(),
+        );
+        return;
+    }
 
-    let mut bms = PylontechBms::new(device);
+    if args.replay {
+        let capture = std::fs::read(device).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {e}", device.display());
+            std::process::exit(1);
+        });
+        let replay = Replay::new(&capture).unwrap_or_else(|e| {
+            eprintln!("Invalid capture: {e}");
+            std::process::exit(2);
+        });
+        run(replay, &args);
+        return;
+    }
 
-    match args.command {
-        Commands::GetProtocolVersion => {
-            println!("{}", bms.get_protocol_version().unwrap())
+    match Device::parse(device) {
+        Device::Serial(path) => {
+            let port = serialport::new(Cow::from(path.to_str().unwrap()), args.baud)
+                .timeout(timeout)
+                .open()
+                .unwrap();
+            run_transport(Serial(FromStd::new(port)), &args);
+        }
+        Device::Tcp(address) => run_transport(connected(Tcp::connect(address, timeout)), &args),
+        Device::TcpListen(address) => {
+            let listener = connected(Tcp::listen(address));
+            run_transport(connected(Tcp::accept(&listener, timeout)), &args)
         }
-        Commands::GetSystemParameter => println!("{}", bms.get_system_parameter().unwrap()),
+        Device::Rfc2217(address) => {
+            run_transport(connected(tcp::rfc2217(address, args.baud, timeout)), &args)
+        }
+    }
+}
+
+/// The connection of `result`, exits if connecting failed
+fn connected<T>(result: std::io::Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("Failed to connect: {e}");
+        std::process::exit(1);
+    })
+}
+
+/// Sniff on `transport` or run the command of `args`
+fn run_transport<T: Read + Write + ReadReady>(transport: T, args: &Args) {
+    if let Commands::Sniff = args.command {
+        sniff::run(
+            ToStd::new(transport),
+            args.decode_options(true),
+            args.flavor,
+        );
+    } else {
+        run_capturing(transport, args);
+    }
+}
+
+/// Run the command of `args` on `uart`, recording the traffic if `--capture` is given
+fn run_capturing<T: Read + Write + ReadReady>(uart: T, args: &Args) {
+    match &args.capture {
+        Some(path) => {
+            let file = std::fs::File::create(path).unwrap_or_else(|e| {
+                eprintln!("Failed to create {}: {e}", path.display());
+                std::process::exit(1);
+            });
+            let start = Instant::now();
+            let recorder = Recorder::new(uart, FromStd::new(BufWriter::new(file)), move || {
+                start.elapsed().as_micros() as u64
+            });
+            if let Err(e) = run(recorder, args).finish() {
+                eprintln!("Failed to write {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+        None => {
+            run(uart, args);
+        }
+    }
+}
+
+/// Run the command of `args` on `uart`, returns the transport
+fn run<T: Read + Write + ReadReady>(uart: T, args: &Args) -> T {
+    let mut bms = PylontechBms::new(uart).with_delay(StdDelay);
+    bms.set_decode_options(args.decode_options(args.resync));
+    bms.drain_before_request(args.resync);
+    if args.retries > 0 {
+        bms.set_retry_policy(RetryPolicy {
+            max_attempts: args.retries.saturating_add(1),
+            ..RetryPolicy::TRANSIENT
+        });
+    }
+
+    match args.command {
+        Commands::GetProtocolVersion => println!("{}", answered(bms.get_protocol_version())),
+        Commands::GetSystemParameter => println!("{}", answered(bms.get_system_parameter())),
         Commands::GetAnalogValue { pack_address } => {
             get_and_print_analog_values(&mut bms, pack_address, args.flavor)
         }
+        Commands::Scan {
+            first,
+            last,
+            analog_value,
+        } => scan(&mut bms, first..=last, analog_value),
+        Commands::Emulate(_) | Commands::Sniff | Commands::Decode { .. } => unreachable!(),
+    }
+    if bms.skipped_bytes() > 0 {
+        eprintln!("Skipped {} bytes of invalid input", bms.skipped_bytes());
+    }
+    if !bms.decode_warnings().is_empty() {
+        eprintln!(
+            "Response deviated from specification: {:?}",
+            bms.decode_warnings()
+        );
+    }
+    bms.into_inner()
+}
+
+/// The response of `result`, exits if the request failed
+fn answered<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("Request failed: {e}");
+        std::process::exit(1);
+    })
+}
+
+/// Delay sleeping the current thread
+struct StdDelay;
+
+impl DelayNs for StdDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        std::thread::sleep(Duration::from_nanos(ns.into()));
+    }
+}
+
+/// Serial port reporting whether input is ready
+struct Serial(FromStd<Box<dyn serialport::SerialPort>>);
+
+impl ErrorType for Serial {
+    type Error = std::io::Error;
+}
+impl Read for Serial {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+}
+impl ReadReady for Serial {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.0.inner().bytes_to_read()? > 0)
+    }
+}
+impl Write for Serial {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush()
     }
 }
 
 fn get_and_print_analog_values<T: Read + Write>(
-    bms: &mut PylontechBms<T>,
+    bms: &mut PylontechBms<T, Pylontech, StdDelay>,
     adr: Option<u8>,
     flavor: Option<Flavor>,
 ) {
     let mut buf = [0; pylon_lfp_protocol::MAX_UNENCODED_PAYLOAD_LEN];
-    let measurements = bms.get_analog_value(adr.unwrap_or(0xFF), &mut buf).unwrap();
+    let measurements = answered(bms.get_analog_value(adr.unwrap_or(0xFF), &mut buf));
+    let scaling = Flavor::scaling(flavor, &measurements);
     if measurements.flags.switch_change() {
         println!("!!!!!!!!!!!!!!!!!!!!!!!!!!");
         println!("!! Unread switch change !!");
@@ -99,37 +411,59 @@ fn get_and_print_analog_values<T: Read + Write>(
         println!("=========");
         println!("Pack {i}:");
         println!("=========");
-        match flavor {
-            Some(Flavor::Superpack) => {
-                let pack: PackData<'_, MILLI, CENTI, CENTI, CENTI> =
-                    measurements.get_pack(i).unwrap();
-                print_pack(pack);
-            }
-            None => {
-                let pack: PackData<'_> = measurements.get_pack(i).unwrap();
-                print_pack(pack);
+        match measurements.get_pack_scaled(i, scaling) {
+            Ok(pack) => print_pack(pack),
+            Err(e) => {
+                eprintln!("Invalid pack data: {e:?}");
+                std::process::exit(1);
             }
         }
     }
 }
 
-fn print_pack<
-    const CELL_VOLT_EXP: i8,
-    const TOTAL_VOLT_EXP: i8,
-    const CURRENT_EXP: i8,
-    const AMP_HOUR_EXP: i8,
->(
-    pack: PackData<'_, CELL_VOLT_EXP, TOTAL_VOLT_EXP, CURRENT_EXP, AMP_HOUR_EXP>,
+fn scan<T: Read + Write + ReadReady>(
+    bms: &mut PylontechBms<T, Pylontech, StdDelay>,
+    addresses: RangeInclusive<u8>,
+    analog_value: bool,
 ) {
-    for (n, v) in pack.cell_voltages.iter().enumerate() {
-        println!("Voltage {n}: {v}");
+    let mut responding = 0;
+    for result in bms.scan(addresses).analog_value(analog_value).drain(true) {
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Scan aborted: {e}");
+                break;
+            }
+        };
+        if !result.responded() {
+            eprintln!("{:#04X}: no response", result.address);
+            continue;
+        }
+        responding += 1;
+        let version = match result.protocol_version {
+            Probe::Response(version) => version.to_string(),
+            probe => format!("{probe:?}"),
+        };
+        print!("{:#04X}: protocol version {version}", result.address);
+        match result.analog_value {
+            Some(Probe::Response(())) => println!(", analog value ok"),
+            Some(probe) => println!(", analog value {probe:?}"),
+            None => println!(),
+        }
+    }
+    println!("{responding} addresses responded");
+}
+
+fn print_pack(pack: ScaledPackData<'_>) {
+    for (n, v) in pack.cell_voltages().enumerate() {
+        println!("Voltage {n}: {v:.3} V");
     }
-    for (n, t) in pack.temperatures.iter().enumerate() {
-        println!("Temp {n}: {:#}", t);
+    for (n, t) in pack.temperatures().enumerate() {
+        println!("Temp {n}: {:.1} °C", t - 273.15);
     }
-    println!("Current: {}", pack.pack_current);
-    println!("Total Voltage: {}", pack.pack_voltage);
-    println!("Remaining capacity: {}", pack.pack_remaining);
-    println!("Total capacity: {}", pack.total_capacity);
-    println!("Cell cycles: {}", pack.cell_cycles);
+    println!("Current: {:.2} A", pack.pack_current());
+    println!("Total Voltage: {:.2} V", pack.pack_voltage());
+    println!("Remaining capacity: {:.2} Ah", pack.pack_remaining());
+    println!("Total capacity: {:.2} Ah", pack.total_capacity());
+    println!("Cell cycles: {}", pack.cell_cycles());
 }
@@ -0,0 +1,84 @@
+//! `sniff` subcommand decoding the traffic on a tapped line
+
+use std::{
+    io::{ErrorKind, Read},
+    time::{Duration, Instant},
+};
+
+use pylon_lfp_protocol::{
+    DecodeOptions, MAX_UNENCODED_PAYLOAD_LEN,
+    sniffer::{Response, Sniffed, Sniffer},
+};
+
+use crate::{Flavor, print_pack};
+
+/// Print the frames on the line of `port` until reading fails
+pub fn run(mut port: impl Read, options: DecodeOptions, flavor: Option<Flavor>) {
+    let mut info_buf = [0u8; MAX_UNENCODED_PAYLOAD_LEN];
+    let mut sniffer: Sniffer<'_> = Sniffer::new(&mut info_buf, options);
+    let start = Instant::now();
+    let mut buf = [0u8; 256];
+    loop {
+        let mut data = match port.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => &buf[..len],
+            Err(e) if e.kind() == ErrorKind::TimedOut => continue,
+            Err(e) => {
+                eprintln!("Transport error: {e}");
+                break;
+            }
+        };
+        while !data.is_empty() {
+            let (consumed, event) = sniffer.push(data);
+            data = &data[consumed..];
+            if let Some(event) = event {
+                print_event(start.elapsed(), event, flavor);
+            }
+        }
+    }
+}
+
+fn print_event(time: Duration, event: Sniffed<'_>, flavor: Option<Flavor>) {
+    let time = time.as_secs_f32();
+    match event {
+        Sniffed::Request {
+            adr,
+            request,
+            unanswered,
+        } => {
+            if let Some(command) = unanswered {
+                println!("[{time:10.3}] -- No response to {command:?}");
+            }
+            println!("[{time:10.3}] -> {adr:#04X} {request:?}");
+        }
+        Sniffed::Response {
+            adr,
+            command,
+            response,
+        } => {
+            let command = command.map_or("missed command".into(), |command| format!("{command:?}"));
+            print!("[{time:10.3}] <- {adr:#04X} {command}: ");
+            match response {
+                Response::ProtocolVersion(version) => println!("{version}"),
+                Response::SystemParameter(parameter) => println!("\n{parameter}"),
+                Response::AnalogValue(measurements) => {
+                    println!("{} packs", measurements.get_pack_count());
+                    let scaling = Flavor::scaling(flavor, &measurements);
+                    for i in 0..measurements.get_pack_count() {
+                        println!("--- Pack {i} ---");
+                        match measurements.get_pack_scaled(i, scaling) {
+                            Ok(pack) => print_pack(pack),
+                            Err(e) => println!("Invalid pack data: {e:?}"),
+                        }
+                    }
+                }
+                Response::AlarmInfo(alarms) => println!("{alarms:?}"),
+                Response::Other(info) => println!("{info:02X?}"),
+                Response::Error(code) => println!("{code:?}"),
+                _ => println!("Unknown response"),
+            }
+        }
+        Sniffed::Invalid(e) => println!("[{time:10.3}] !! Invalid frame: {e:?}"),
+        _ => println!("[{time:10.3}] !! Unknown event"),
+    }
+}
@@ -0,0 +1,158 @@
+//! TCP transport for serial-to-Ethernet converters in transparent mode or speaking RFC 2217
+
+use std::{
+    io::{self, ErrorKind},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use embedded_io::{ErrorType, ReadReady};
+use pylon_lfp_protocol::rfc2217::{Parity, Rfc2217, StopBits};
+
+/// Connection to a converter forwarding the bytes of a serial line
+///
+/// Implements the `std::io` traits as well as the `embedded_io` traits.
+/// Reads time out with [ErrorKind::TimedOut] like serial ports and fail with
+/// [ErrorKind::UnexpectedEof] once the peer closed the connection.
+pub struct Tcp(TcpStream);
+
+impl Tcp {
+    /// Connect to the converter at `address` (`host:port`)
+    pub fn connect(address: &str, timeout: Duration) -> io::Result<Self> {
+        Self::new(TcpStream::connect(address)?, timeout)
+    }
+    /// Listen for converters connecting to `address` (`host:port`)
+    pub fn listen(address: &str) -> io::Result<TcpListener> {
+        let listener = TcpListener::bind(address)?;
+        eprintln!("Waiting for a connection on {}", listener.local_addr()?);
+        Ok(listener)
+    }
+    /// Wait for the next converter connecting to `listener`
+    pub fn accept(listener: &TcpListener, timeout: Duration) -> io::Result<Self> {
+        let (stream, peer) = listener.accept()?;
+        eprintln!("Accepted connection from {peer}");
+        Self::new(stream, timeout)
+    }
+    fn new(stream: TcpStream, timeout: Duration) -> io::Result<Self> {
+        // Send requests right away instead of waiting for more data
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(timeout))?;
+        Ok(Tcp(stream))
+    }
+}
+
+/// Connect to the RFC 2217 server at `address` (`host:port`) and configure the line for 8N1
+pub fn rfc2217(address: &str, baud: u32, timeout: Duration) -> io::Result<Rfc2217<Tcp>> {
+    let mut port = Rfc2217::new(Tcp::connect(address, timeout)?)?;
+    port.set_baud_rate(baud)?;
+    port.set_data_bits(8)?;
+    port.set_parity(Parity::None)?;
+    port.set_stop_bits(StopBits::One)?;
+    Ok(port)
+}
+
+impl io::Read for Tcp {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Read timeouts surface as `WouldBlock` on Unix
+        match self.0.read(buf) {
+            // Fail instead of reporting an empty read on every call
+            Ok(0) if !buf.is_empty() => Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "connection closed",
+            )),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Err(ErrorKind::TimedOut.into()),
+            read => read,
+        }
+    }
+}
+impl io::Write for Tcp {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl ErrorType for Tcp {
+    type Error = io::Error;
+}
+impl embedded_io::Read for Tcp {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        io::Read::read(self, buf)
+    }
+}
+impl ReadReady for Tcp {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        self.0.set_nonblocking(true)?;
+        let ready = match self.0.peek(&mut [0]) {
+            // The end of the stream is ready to be read as well
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.0.set_nonblocking(false)?;
+        ready
+    }
+}
+impl embedded_io::Write for Tcp {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        io::Write::write(self, buf)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        io::Write::flush(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+        time::Duration,
+    };
+
+    use pylon_lfp_protocol::PylontechBms;
+
+    use super::Tcp;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+    /// "_get protocol version_" request of pack 1 and its response
+    const REQUEST: &[u8] = b"~2801464F0000FD91\r";
+    const RESPONSE: &[u8] = b"~280146000000FDAB\r";
+
+    /// Answer a single version request on `stream` and close it
+    fn answer(mut stream: TcpStream) {
+        let mut request = [0; REQUEST.len()];
+        stream.read_exact(&mut request).unwrap();
+        assert_eq!(request, REQUEST);
+        stream.write_all(RESPONSE).unwrap();
+    }
+
+    /// Request the protocol version on `tcp` and check the end of input after it
+    fn request_version(tcp: Tcp) {
+        let mut bms: PylontechBms<_> = PylontechBms::new(tcp);
+        assert_eq!(bms.get_protocol_version().unwrap().to_string(), "v2.8");
+        let mut tcp = bms.into_inner();
+        let e = Read::read(&mut tcp, &mut [0; 8]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let peer = thread::spawn(move || answer(listener.accept().unwrap().0));
+        request_version(Tcp::connect(&address, TIMEOUT).unwrap());
+        peer.join().unwrap();
+    }
+    #[test]
+    fn accept() {
+        let listener = Tcp::listen("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let peer = thread::spawn(move || answer(TcpStream::connect(address).unwrap()));
+        request_version(Tcp::accept(&listener, TIMEOUT).unwrap());
+        peer.join().unwrap();
+    }
+}